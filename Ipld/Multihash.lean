@@ -35,4 +35,16 @@ def sha3_256 (x: ByteArray) : Multihash :=
 def sha3_512 (x: ByteArray) : Multihash :=
   { code := 0x14, size := 64, digest := Keccak.sha3_512 x }
 
+def keccak224 (x: ByteArray) : Multihash :=
+  { code := 0x1a, size := 28, digest := Keccak.keccak224 x }
+
+def keccak256 (x: ByteArray) : Multihash :=
+  { code := 0x1b, size := 32, digest := Keccak.keccak256 x }
+
+def keccak384 (x: ByteArray) : Multihash :=
+  { code := 0x1c, size := 48, digest := Keccak.keccak384 x }
+
+def keccak512 (x: ByteArray) : Multihash :=
+  { code := 0x1d, size := 64, digest := Keccak.keccak512 x }
+
 end Multihash
@@ -0,0 +1,54 @@
+import Ipld.Multihash
+import LSpec
+
+open Multihash
+
+/-- Vectors shared with the Rust test suite, so that both implementations
+    produce the same multihashes. Each line holds the hash function, the input
+    and its multihash, both in base16 multibase. The path is relative to the
+    repository root -/
+def fixture : System.FilePath := "ipld-rs" / "tests" / "fixtures" / "keccak.txt"
+
+/-- Finds the fixture under `dir` or the closest of its ancestors, so that the
+    test runs from the repository root as well as from anywhere inside it -/
+partial def findFixture (dir : System.FilePath) : IO (Option System.FilePath) := do
+  let path := dir / fixture
+  if ← path.pathExists then return some path
+  match dir.parent with
+  | some parent => if parent == dir then return none else findFixture parent
+  | none => return none
+
+def hashFunction : String → Option (ByteArray → Multihash)
+  | "keccak-224" => some keccak224
+  | "keccak-256" => some keccak256
+  | "keccak-384" => some keccak384
+  | "keccak-512" => some keccak512
+  | _ => none
+
+/-- Test that a given line of the fixture passes -/
+def testLine (line : String) : Bool :=
+  match line.splitOn " " with
+  | [name, input, hash] =>
+    match hashFunction name, Base.b16.toMultibase.decodeBytes input,
+      Base.b16.toMultibase.decodeBytes hash with
+    | some f, some input, some hash => toBytes (f input) == hash
+    | _, _, _ => false
+  | _ => false
+
+open LSpec in
+/-- Reads the fixture from the path given as the only argument, or else looks
+    for it above the working directory and then above the executable -/
+def main (args : List String) : IO UInt32 := do
+  let path ← match args with
+    | [path] => pure (some (System.FilePath.mk path))
+    | _ => do
+      if let some path ← findFixture (← IO.currentDir) then
+        pure (some path)
+      else
+        findFixture ((← IO.appPath).parent.getD ".")
+  let some path := path | do
+    IO.eprintln s!"cannot find {fixture}, pass its path as an argument"
+    return 1
+  let lines := (← IO.FS.lines path).toList.filter (· != "")
+  lspecIO $
+    test "matches the shared Keccak fixture" (lines.all testLine)
@@ -0,0 +1,199 @@
+//! Blocks: encoded IPLD data together with the CID that names it.
+
+use crate::{
+  cid::Cid,
+  codec::{
+    Codec,
+    Registry,
+  },
+  error::{
+    BlockError,
+    Error,
+  },
+  ipld::Ipld,
+  multihash::{
+    Code,
+    Multihash,
+  },
+};
+
+/// The shortest truncated digest a block is verified against. Shorter
+/// digests, down to an empty one, would match almost any data.
+pub const MIN_DIGEST_LEN: u64 = 20;
+
+/// The bytes of a block and its CID. Blocks built by [`Block::encode`] or
+/// [`Block::new`] always match their CID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+  cid: Cid,
+  data: Vec<u8>,
+}
+
+impl Block {
+  /// Pairs `data` with `cid`, checking that the data hashes to it.
+  pub fn new(cid: Cid, data: Vec<u8>) -> Result<Self, Error> {
+    let block = Block { cid, data };
+    block.verify()?;
+    Ok(block)
+  }
+
+  /// Pairs `data` with `cid` without hashing it, for data that has already
+  /// been verified.
+  pub fn new_unchecked(cid: Cid, data: Vec<u8>) -> Self { Block { cid, data } }
+
+  /// Encodes `ipld` with `codec` and names it with a CIDv1 using the hash
+  /// function `code`.
+  pub fn encode<C: Codec + ?Sized>(
+    codec: &C,
+    code: Code,
+    ipld: &Ipld,
+  ) -> Result<Self, Error> {
+    let data = codec.encode(ipld)?;
+    let cid = Cid::new_v1(codec.code(), Multihash::hash(code, &data));
+    Ok(Block { cid, data })
+  }
+
+  pub fn cid(&self) -> &Cid { &self.cid }
+
+  pub fn data(&self) -> &[u8] { &self.data }
+
+  pub fn into_inner(self) -> (Cid, Vec<u8>) { (self.cid, self.data) }
+
+  /// Decodes the block with the built-in codec named by its CID.
+  pub fn decode(&self) -> Result<Ipld, Error> {
+    self.decode_with(&Registry::default())
+  }
+
+  /// Decodes the block with the codec `registry` holds for its CID.
+  pub fn decode_with(&self, registry: &Registry) -> Result<Ipld, Error> {
+    registry.decode(&self.cid, &self.data)
+  }
+
+  /// Hashes the data again and checks it against the multihash in the CID,
+  /// which may be truncated to no less than [`MIN_DIGEST_LEN`] bytes.
+  pub fn verify(&self) -> Result<(), BlockError> {
+    let hash = &self.cid.hash;
+    let code = Code::from_code(hash.code())
+      .ok_or(BlockError::UnsupportedHash(hash.code()))?;
+    if code != Code::Identity && hash.size() < MIN_DIGEST_LEN {
+      return Err(BlockError::DigestTooShort(hash.size()));
+    }
+    let digest = Multihash::hash(code, &self.data);
+    let matches = if code == Code::Identity {
+      digest == *hash
+    }
+    else {
+      digest.digest().starts_with(hash.digest())
+    };
+    if matches {
+      Ok(())
+    }
+    else {
+      Err(BlockError::HashMismatch(self.cid.clone()))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    block::Block,
+    cid::Cid,
+    codec::{
+      DagCborCodec,
+      DagJsonCodec,
+      RawCodec,
+      DAG_CBOR,
+    },
+    error::{
+      BlockError,
+      Error,
+    },
+    ipld::Ipld,
+    multihash::{
+      Code,
+      Multihash,
+    },
+  };
+
+  #[test]
+  fn encode_decode() {
+    let ipld = Ipld::to_object(vec![("a".into(), Ipld::Integer(1))]);
+    let block = Block::encode(&DagCborCodec, Code::Sha2_256, &ipld).unwrap();
+    assert_eq!(block.data(), [0xa1, 0x61, 0x61, 0x01]);
+    assert_eq!(block.cid().version, 1);
+    assert_eq!(block.cid().codec, DAG_CBOR);
+    assert_eq!(block.cid().hash, Multihash::hash(Code::Sha2_256, block.data()));
+    assert_eq!(block.decode().unwrap(), ipld);
+    assert_eq!(block.verify(), Ok(()));
+
+    let json = Block::encode(&DagJsonCodec, Code::Blake3, &ipld).unwrap();
+    assert_eq!(json.data(), br#"{"a":1}"#);
+    assert_eq!(json.decode().unwrap(), ipld);
+    let (cid, data) = json.clone().into_inner();
+    assert_eq!(Block::new(cid, data).unwrap(), json);
+  }
+
+  #[test]
+  fn published_cid() {
+    // The empty raw block, as named by IPFS
+    let block =
+      Block::encode(&RawCodec, Code::Sha2_256, &Ipld::Bytes(vec![])).unwrap();
+    assert_eq!(
+      block.cid().to_string(),
+      "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku"
+    );
+  }
+
+  #[test]
+  fn verify_mismatch() {
+    let block =
+      Block::encode(&RawCodec, Code::Sha3_256, &Ipld::Bytes(vec![1])).unwrap();
+    let (cid, _) = block.into_inner();
+    assert_eq!(
+      Block::new(cid.clone(), vec![2]),
+      Err(Error::Block(BlockError::HashMismatch(cid.clone())))
+    );
+    assert_eq!(
+      Block::new_unchecked(cid.clone(), vec![2]).verify(),
+      Err(BlockError::HashMismatch(cid))
+    );
+
+    let unknown = Multihash::from_bytes(&mut &[0x99, 0x01, 0x00][..]).unwrap();
+    let cid = Cid::new_v1(0x55, unknown);
+    assert_eq!(
+      Block::new_unchecked(cid, vec![0]).verify(),
+      Err(BlockError::UnsupportedHash(0x99))
+    );
+  }
+
+  #[test]
+  fn verify_identity_and_truncated() {
+    let identity =
+      Block::encode(&RawCodec, Code::Identity, &Ipld::Bytes(vec![7])).unwrap();
+    assert_eq!(identity.cid().hash.digest(), [7]);
+    assert_eq!(identity.verify(), Ok(()));
+    let (cid, _) = identity.into_inner();
+    assert!(Block::new(cid, vec![7, 7]).is_err());
+
+    // A sha2-256 digest cut to 20 bytes still names the block
+    let full = Multihash::hash(Code::Sha2_256, b"x").to_bytes();
+    let mut truncated = vec![0x12, 20];
+    truncated.extend(&full[2..22]);
+    let hash = Multihash::from_bytes(&mut &truncated[..]).unwrap();
+    let cid = Cid::new_v1(0x55, hash);
+    assert!(Block::new(cid.clone(), b"x".to_vec()).is_ok());
+    assert!(Block::new(cid, b"y".to_vec()).is_err());
+
+    // Shorter digests would match almost any data, `0x12 0x00` all of it
+    for size in [0, 19] {
+      let mut short = vec![0x12, size as u8];
+      short.extend(&full[2..2 + size]);
+      let hash = Multihash::from_bytes(&mut &short[..]).unwrap();
+      assert_eq!(
+        Block::new(Cid::new_v1(0x55, hash), b"x".to_vec()),
+        Err(Error::Block(BlockError::DigestTooShort(size as u64)))
+      );
+    }
+  }
+}
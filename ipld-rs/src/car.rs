@@ -0,0 +1,557 @@
+//! CARv1 archives: a length-prefixed DAG-CBOR header naming the root CIDs,
+//! followed by sections holding the bytes of a CID and of its block, each
+//! prefixed with its length as an unsigned varint.
+
+use std::io::{
+  self,
+  Read,
+  Write,
+};
+
+use crate::{
+  block::Block,
+  cid::Cid,
+  dag_cbor,
+  error::{
+    CarError,
+    Error,
+    VarintError,
+  },
+  ipld::Ipld,
+  unsigned_varint::{
+    encoded_len,
+    to_varint,
+    varint_read_u64,
+  },
+};
+
+/// The header at the start of a CARv1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CarHeader {
+  pub roots: Vec<Cid>,
+}
+
+impl CarHeader {
+  /// Fails only if a root cannot be encoded.
+  pub fn to_bytes(&self) -> Result<Vec<u8>, CarError> {
+    let roots = self.roots.iter().cloned().map(Ipld::Link).collect();
+    let ipld = Ipld::to_object(vec![
+      ("roots".into(), Ipld::Array(roots)),
+      ("version".into(), Ipld::Integer(1)),
+    ]);
+    dag_cbor::serialize(&ipld).map_err(CarError::Header)
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<Self, CarError> {
+    let mut rest = bytes;
+    let ipld = dag_cbor::deserialize(&mut rest).map_err(CarError::Header)?;
+    if !rest.is_empty() {
+      return Err(CarError::InvalidHeader("trailing bytes"));
+    }
+    let map = match ipld {
+      Ipld::Object(map) => map,
+      _ => return Err(CarError::InvalidHeader("not a map")),
+    };
+    match map.get("version") {
+      Some(Ipld::Integer(1)) => (),
+      Some(Ipld::Integer(v)) if *v >= 0 && *v <= u64::MAX as i128 => {
+        return Err(CarError::UnsupportedVersion(*v as u64));
+      }
+      Some(_) => return Err(CarError::InvalidHeader("invalid version")),
+      None => return Err(CarError::InvalidHeader("missing version")),
+    }
+    let roots = match map.get("roots") {
+      Some(Ipld::Array(roots)) => roots,
+      _ => return Err(CarError::InvalidHeader("missing roots")),
+    };
+    let roots = roots
+      .iter()
+      .map(|root| match root {
+        Ipld::Link(cid) => Ok(cid.clone()),
+        _ => Err(CarError::InvalidHeader("root is not a link")),
+      })
+      .collect::<Result<_, _>>()?;
+    Ok(CarHeader { roots })
+  }
+}
+
+// Reads a varint length prefix, returning `None` at the end of the input
+fn read_length<R: Read>(
+  r: &mut R,
+  offset: u64,
+) -> Result<Option<u64>, CarError> {
+  match varint_read_u64(r) {
+    Ok(len) => Ok(Some(len)),
+    Err(VarintError::UnexpectedEof { offset: 0 }) => Ok(None),
+    Err(VarintError::Io(kind)) => Err(CarError::Io { offset, kind }),
+    Err(source) => Err(CarError::Length { offset, source }),
+  }
+}
+
+/// Bounds on the lengths a [`CarReader`] accepts, so that a bogus length
+/// prefix cannot make it buffer an untrusted stream without end. Lengths are
+/// checked against the limits before anything is read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CarLimits {
+  /// Maximum length in bytes of the header.
+  pub max_header_len: u64,
+  /// Maximum length in bytes of a section, CID and block together.
+  pub max_section_len: u64,
+}
+
+impl CarLimits {
+  /// No limits besides the length of the input.
+  pub fn unlimited() -> Self {
+    CarLimits { max_header_len: u64::MAX, max_section_len: u64::MAX }
+  }
+}
+
+/// The defaults of go-car: 32 MiB for the header and for each section.
+impl Default for CarLimits {
+  fn default() -> Self {
+    CarLimits { max_header_len: 32 << 20, max_section_len: 32 << 20 }
+  }
+}
+
+// Reads exactly `len` bytes, without trusting `len` for the allocation
+pub(crate) fn read_bytes<R: Read>(
+  r: &mut R,
+  len: u64,
+  offset: u64,
+) -> Result<Vec<u8>, CarError> {
+  let mut bytes = vec![];
+  r.take(len).read_to_end(&mut bytes).map_err(|e| CarError::Io {
+    offset: offset + bytes.len() as u64,
+    kind: e.kind(),
+  })?;
+  if (bytes.len() as u64) < len {
+    return Err(CarError::UnexpectedEof {
+      offset: offset + bytes.len() as u64,
+    });
+  }
+  Ok(bytes)
+}
+
+/// Reads the section starting at `start`, returning its verified block and
+/// the offset where it ends, or `None` at the end of the input.
+pub(crate) fn read_section<R: Read>(
+  r: &mut R,
+  start: u64,
+  limits: &CarLimits,
+) -> Result<Option<(Block, u64)>, Error> {
+  let len = match read_length(r, start)? {
+    Some(len) => len,
+    None => return Ok(None),
+  };
+  if len > limits.max_section_len {
+    return Err(CarError::SectionLimit { len, offset: start }.into());
+  }
+  let offset = start + encoded_len(len) as u64;
+  let bytes = read_bytes(r, len, offset)?;
+  let mut data = &bytes[..];
+  let cid = Cid::from_bytes(&mut data)
+    .map_err(|source| CarError::Cid { offset, source })?;
+  let block = Block::new(cid, data.to_vec())?;
+  Ok(Some((block, offset + len)))
+}
+
+/// Reads the blocks of a CARv1 from any reader, checking each one against its
+/// CID.
+pub struct CarReader<R> {
+  inner: R,
+  header: CarHeader,
+  limits: CarLimits,
+  offset: u64,
+  done: bool,
+}
+
+impl<R: Read> CarReader<R> {
+  /// Reads the header, leaving the reader at the first block.
+  pub fn new(inner: R) -> Result<Self, Error> {
+    Self::with_limits(inner, CarLimits::default())
+  }
+
+  /// Like [`CarReader::new`], failing on any header or section longer than
+  /// `limits` allow.
+  pub fn with_limits(mut inner: R, limits: CarLimits) -> Result<Self, Error> {
+    let len = read_length(&mut inner, 0)?
+      .ok_or(CarError::UnexpectedEof { offset: 0 })?;
+    if len > limits.max_header_len {
+      return Err(CarError::HeaderLimit { len }.into());
+    }
+    let offset = encoded_len(len) as u64;
+    let bytes = read_bytes(&mut inner, len, offset)?;
+    let header = CarHeader::from_bytes(&bytes)?;
+    Ok(CarReader { inner, header, limits, offset: offset + len, done: false })
+  }
+
+  pub fn header(&self) -> &CarHeader { &self.header }
+
+  pub fn roots(&self) -> &[Cid] { &self.header.roots }
+
+  /// Returns the offset of the next section from the start of the archive.
+  pub fn offset(&self) -> u64 { self.offset }
+
+  /// Returns the next block, or `None` at the end of the archive.
+  pub fn next_block(&mut self) -> Result<Option<Block>, Error> {
+    match read_section(&mut self.inner, self.offset, &self.limits)? {
+      Some((block, end)) => {
+        self.offset = end;
+        Ok(Some(block))
+      }
+      None => Ok(None),
+    }
+  }
+
+  pub fn into_inner(self) -> R { self.inner }
+}
+
+/// Iterates over the blocks, stopping after the first error.
+impl<R: Read> Iterator for CarReader<R> {
+  type Item = Result<Block, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+    let next = self.next_block().transpose();
+    self.done = !matches!(next, Some(Ok(_)));
+    next
+  }
+}
+
+/// Writes a CARv1 to any writer: the header as soon as it is created, then
+/// each block as it is given.
+pub struct CarWriter<W> {
+  inner: W,
+  offset: u64,
+}
+
+impl<W: Write> CarWriter<W> {
+  pub fn new(inner: W, roots: Vec<Cid>) -> Result<Self, Error> {
+    let mut writer = CarWriter { inner, offset: 0 };
+    writer.write_section(&[&CarHeader { roots }.to_bytes()?])?;
+    Ok(writer)
+  }
+
+  pub fn write(&mut self, block: &Block) -> Result<(), Error> {
+    self.write_section(&[&block.cid().to_bytes()?, block.data()])
+  }
+
+  // Writes the concatenation of `parts` behind its length
+  fn write_section(&mut self, parts: &[&[u8]]) -> Result<(), Error> {
+    let len: usize = parts.iter().map(|part| part.len()).sum();
+    self.write_all(&to_varint(len as u64)?)?;
+    for part in parts {
+      self.write_all(part)?;
+    }
+    Ok(())
+  }
+
+  fn write_all(&mut self, bytes: &[u8]) -> Result<(), CarError> {
+    let offset = self.offset;
+    self.inner.write_all(bytes).map_err(|e| io_error(e, offset))?;
+    self.offset += bytes.len() as u64;
+    Ok(())
+  }
+
+  /// Flushes the archive and returns the writer.
+  pub fn finish(mut self) -> Result<W, Error> {
+    let offset = self.offset;
+    self.inner.flush().map_err(|e| io_error(e, offset))?;
+    Ok(self.inner)
+  }
+}
+
+fn io_error(e: io::Error, offset: u64) -> CarError {
+  CarError::Io { offset, kind: e.kind() }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::{
+    self,
+    Read,
+  };
+
+  use crate::{
+    block::Block,
+    car::{
+      CarHeader,
+      CarLimits,
+      CarReader,
+      CarWriter,
+    },
+    cid::Cid,
+    codec::{
+      DagCborCodec,
+      RawCodec,
+    },
+    error::{
+      BlockError,
+      CarError,
+      CidError,
+      DagCborError,
+      Error,
+      VarintError,
+    },
+    ipld::Ipld,
+    multihash::Code,
+    test_util::hex,
+  };
+
+  // A DAG-CBOR root linking to the raw block "hello", written by hand from
+  // the CARv1 spec
+  const BASIC: &str = concat!(
+    "3aa265726f6f747381d82a58250001711220a76ef08162b66e765ba38cd954a31c54df",
+    "b7f38e444d9dc7273160e25c7391d06776657273696f6e01",
+    "5c01711220a76ef08162b66e765ba38cd954a31c54dfb7f38e444d9dc7273160e25c73",
+    "91d0a2646c696e6bd82a582500015512202cf24dba5fb0a30e26e83b2ac5b9e29e1b16",
+    "1e5c1fa7425e73043362938b9824646e616d6563636172",
+    "29015512202cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b",
+    "982468656c6c6f",
+  );
+  const ROOT: &str =
+    "bafyreifhn3yicyvwnz3fxi4m3fkkghcu3637hdsejwo4ojzrmdrfy44r2a";
+  const HELLO: &str =
+    "bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq";
+
+  fn basic_blocks() -> Vec<Block> {
+    let hello =
+      Block::encode(&RawCodec, Code::Sha2_256, &Ipld::Bytes(b"hello".to_vec()))
+        .unwrap();
+    let root = Ipld::to_object(vec![
+      ("link".into(), Ipld::Link(hello.cid().clone())),
+      ("name".into(), Ipld::String("car".into())),
+    ]);
+    let root = Block::encode(&DagCborCodec, Code::Sha2_256, &root).unwrap();
+    vec![root, hello]
+  }
+
+  #[test]
+  fn read_fixture() {
+    let bytes = hex(BASIC);
+    let mut reader = CarReader::new(&bytes[..]).unwrap();
+    assert_eq!(reader.roots(), [ROOT.parse::<Cid>().unwrap()]);
+    let blocks = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(blocks, basic_blocks());
+    assert_eq!(blocks[1].cid().to_string(), HELLO);
+    assert_eq!(reader.next_block(), Ok(None));
+  }
+
+  #[test]
+  fn write_fixture() {
+    let blocks = basic_blocks();
+    let mut writer =
+      CarWriter::new(vec![], vec![blocks[0].cid().clone()]).unwrap();
+    for block in &blocks {
+      writer.write(block).unwrap();
+    }
+    assert_eq!(writer.finish().unwrap(), hex(BASIC));
+  }
+
+  #[test]
+  fn roundtrip_many() {
+    let blocks: Vec<Block> = (0..100)
+      .map(|i| {
+        let data = vec![i as u8; i * 50];
+        Block::encode(&RawCodec, Code::Blake3, &Ipld::Bytes(data)).unwrap()
+      })
+      .collect();
+    let roots = vec![blocks[0].cid().clone(), blocks[99].cid().clone()];
+    let mut writer = CarWriter::new(vec![], roots.clone()).unwrap();
+    for block in &blocks {
+      writer.write(block).unwrap();
+    }
+    let bytes = writer.finish().unwrap();
+    let reader = CarReader::new(&bytes[..]).unwrap();
+    assert_eq!(reader.roots(), roots);
+    assert_eq!(reader.collect::<Result<Vec<_>, _>>().unwrap(), blocks);
+  }
+
+  #[test]
+  fn malformed_header() {
+    let err = |bytes: &[u8]| CarReader::new(bytes).err().unwrap();
+    assert_eq!(err(&[]), Error::Car(CarError::UnexpectedEof { offset: 0 }));
+    assert_eq!(
+      err(&[0x05, 0xa0]),
+      Error::Car(CarError::UnexpectedEof { offset: 2 })
+    );
+    assert_eq!(
+      err(&[0x80]),
+      Error::Car(CarError::Length {
+        offset: 0,
+        source: VarintError::UnexpectedEof { offset: 1 }
+      })
+    );
+    assert!(matches!(err(&[0x01, 0xff]), Error::Car(CarError::Header(_))));
+    assert_eq!(
+      err(&[0x01, 0xf6]),
+      Error::Car(CarError::InvalidHeader("not a map"))
+    );
+    let header = |ipld: Ipld| {
+      let mut bytes = crate::dag_cbor::serialize(&ipld).unwrap();
+      bytes.insert(0, bytes.len() as u8);
+      err(&bytes)
+    };
+    assert_eq!(
+      header(Ipld::to_object(vec![("roots".into(), Ipld::Array(vec![]))])),
+      Error::Car(CarError::InvalidHeader("missing version"))
+    );
+    assert_eq!(
+      header(Ipld::to_object(vec![
+        ("roots".into(), Ipld::Array(vec![])),
+        ("version".into(), Ipld::Integer(2)),
+      ])),
+      Error::Car(CarError::UnsupportedVersion(2))
+    );
+    let version = |version: Ipld| {
+      header(Ipld::to_object(vec![
+        ("roots".into(), Ipld::Array(vec![])),
+        ("version".into(), version),
+      ]))
+    };
+    assert_eq!(
+      version(Ipld::Integer(0)),
+      Error::Car(CarError::UnsupportedVersion(0))
+    );
+    assert_eq!(
+      version(Ipld::Integer(-1)),
+      Error::Car(CarError::InvalidHeader("invalid version"))
+    );
+    assert_eq!(
+      version(Ipld::String("1".into())),
+      Error::Car(CarError::InvalidHeader("invalid version"))
+    );
+    assert_eq!(
+      header(Ipld::to_object(vec![("version".into(), Ipld::Integer(1))])),
+      Error::Car(CarError::InvalidHeader("missing roots"))
+    );
+    assert_eq!(
+      header(Ipld::to_object(vec![
+        ("roots".into(), Ipld::Array(vec![Ipld::Null])),
+        ("version".into(), Ipld::Integer(1)),
+      ])),
+      Error::Car(CarError::InvalidHeader("root is not a link"))
+    );
+    assert_eq!(
+      CarHeader::from_bytes(&CarHeader { roots: vec![] }.to_bytes().unwrap()),
+      Ok(CarHeader { roots: vec![] })
+    );
+    // A root that would not read back is not written either
+    let mut root = ROOT.parse::<Cid>().unwrap();
+    root.codec = 1 << 63;
+    assert_eq!(
+      CarWriter::new(vec![], vec![root]).err(),
+      Some(Error::Car(CarError::Header(DagCborError::InvalidLink(
+        CidError::Codec(VarintError::TooLarge(1 << 63))
+      ))))
+    );
+  }
+
+  #[test]
+  fn malformed_sections() {
+    let bytes = hex(BASIC);
+    // Cut inside the last block
+    let mut reader = CarReader::new(&bytes[..bytes.len() - 1]).unwrap();
+    assert!(reader.next_block().unwrap().is_some());
+    assert_eq!(
+      reader.next_block(),
+      Err(Error::Car(CarError::UnexpectedEof { offset: 193 }))
+    );
+
+    // A corrupted block fails verification, and ends the iteration
+    let mut corrupt = bytes.clone();
+    *corrupt.last_mut().unwrap() ^= 1;
+    let results: Vec<_> = CarReader::new(&corrupt[..]).unwrap().collect();
+    assert_eq!(results.len(), 2);
+    assert_eq!(
+      results[1],
+      Err(Error::Block(BlockError::HashMismatch(HELLO.parse().unwrap())))
+    );
+
+    // The CID of the second section starts at byte 59 + 1 + 92 + 1
+    let mut bad_cid = bytes;
+    bad_cid[153] = 2;
+    let mut reader = CarReader::new(&bad_cid[..]).unwrap();
+    reader.next_block().unwrap();
+    assert!(matches!(
+      reader.next_block(),
+      Err(Error::Car(CarError::Cid { offset: 153, .. }))
+    ));
+  }
+
+  #[test]
+  fn limits() {
+    // A length prefix near 2^63 in front of an endless stream fails before
+    // anything is buffered
+    let huge = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+    let endless = huge.chain(io::repeat(0));
+    assert_eq!(
+      CarReader::new(endless).err(),
+      Some(Error::Car(CarError::HeaderLimit { len: (1 << 63) - 1 }))
+    );
+    let bytes = hex(BASIC);
+    let mut endless = bytes[..59].chain(&huge[..]).chain(io::repeat(0));
+    let mut reader = CarReader::new(&mut endless).unwrap();
+    assert_eq!(
+      reader.next_block(),
+      Err(Error::Car(CarError::SectionLimit {
+        len: (1 << 63) - 1,
+        offset: 59
+      }))
+    );
+
+    // The root section is 92 bytes long, the header 58
+    let limits = CarLimits { max_header_len: 58, max_section_len: 91 };
+    let mut reader = CarReader::with_limits(&bytes[..], limits).unwrap();
+    assert_eq!(
+      reader.next_block(),
+      Err(Error::Car(CarError::SectionLimit { len: 92, offset: 59 }))
+    );
+    let limits = CarLimits { max_header_len: 57, ..CarLimits::unlimited() };
+    assert_eq!(
+      CarReader::with_limits(&bytes[..], limits).err(),
+      Some(Error::Car(CarError::HeaderLimit { len: 58 }))
+    );
+    let reader = CarReader::with_limits(&bytes[..], CarLimits::unlimited());
+    assert_eq!(reader.unwrap().count(), 2);
+  }
+
+  // The carv1-basic fixture of the CARv1 spec, mixing DAG-CBOR, DAG-PB and
+  // raw blocks under two roots
+  #[test]
+  fn carv1_basic() {
+    let bytes = include_bytes!("../tests/fixtures/carv1-basic.car");
+    let mut reader = CarReader::new(&bytes[..]).unwrap();
+    let roots: Vec<Cid> = [
+      "bafyreihyrpefhacm6kkp4ql6j6udakdit7g3dmkzfriqfykhjw6cad5lrm",
+      "bafyreidj5idub6mapiupjwjsyyxhyhedxycv4vihfsicm2vt46o7morwlm",
+    ]
+    .iter()
+    .map(|s| s.parse().unwrap())
+    .collect();
+    assert_eq!(reader.roots(), roots);
+    let blocks = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+    let cids: Vec<String> =
+      blocks.iter().map(|block| block.cid().to_string()).collect();
+    assert_eq!(cids, [
+      "bafyreihyrpefhacm6kkp4ql6j6udakdit7g3dmkzfriqfykhjw6cad5lrm",
+      "QmNX6Tffavsya4xgBi2VJQnSuqy9GsxongxZZ9uZBqp16d",
+      "bafkreifw7plhl6mofk6sfvhnfh64qmkq73oeqwl6sloru6rehaoujituke",
+      "QmWXZxVQ9yZfhQxLD35eDR8LiMRsYtHxYqTFCBbJoiJVys",
+      "bafkreiebzrnroamgos2adnbpgw5apo3z4iishhbdx77gldnbk57d4zdio4",
+      "QmdwjhxpxzcMsR3qUuj7vUL8pbA7MgR3GAxWi2GLHjsKCT",
+      "bafkreidbxzk2ryxwwtqxem4l3xyyjvw35yu4tcct4cqeqxwo47zhxgxqwq",
+      "bafyreidj5idub6mapiupjwjsyyxhyhedxycv4vihfsicm2vt46o7morwlm",
+    ]);
+    assert_eq!(blocks[2].data(), b"cccc");
+    assert_eq!(reader.offset(), bytes.len() as u64);
+
+    let mut writer = CarWriter::new(vec![], roots).unwrap();
+    for block in &blocks {
+      writer.write(block).unwrap();
+    }
+    assert_eq!(writer.finish().unwrap(), bytes);
+  }
+}
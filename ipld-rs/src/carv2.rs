@@ -0,0 +1,720 @@
+//! CARv2 archives: a fixed pragma and header, a CARv1 data payload, and an
+//! optional index of where each block's section starts in the payload.
+
+use std::{
+  collections::BTreeMap,
+  io::{
+    self,
+    Read,
+    Seek,
+    SeekFrom,
+    Write,
+  },
+};
+
+use crate::{
+  block::Block,
+  car::{
+    read_bytes,
+    read_section,
+    CarLimits,
+    CarReader,
+  },
+  cid::Cid,
+  error::{
+    CarError,
+    Error,
+    VarintError,
+  },
+  multihash::Multihash,
+  unsigned_varint::{
+    encoded_len,
+    to_varint,
+    varint_read_u64,
+  },
+};
+
+/// The first bytes of every CARv2: a CARv1 style header reading
+/// `{"version": 2}`.
+pub const PRAGMA: [u8; 11] =
+  [0x0a, 0xa1, 0x67, b'v', b'e', b'r', b's', b'i', b'o', b'n', 0x02];
+
+/// Multicodec code of the `IndexSorted` index format.
+pub const INDEX_SORTED: u64 = 0x0400;
+/// Multicodec code of the `MultihashIndexSorted` index format.
+pub const MULTIHASH_INDEX_SORTED: u64 = 0x0401;
+
+const HEADER_LEN: usize = 40;
+
+// Where the data payload starts when written right after the header
+const DATA_OFFSET: u64 = (PRAGMA.len() + HEADER_LEN) as u64;
+
+fn io_error(e: io::Error, offset: u64) -> CarError {
+  CarError::Io { offset, kind: e.kind() }
+}
+
+/// The fixed size header following the pragma. Offsets count bytes from the
+/// start of the CARv2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CarV2Header {
+  pub characteristics: [u8; 16],
+  pub data_offset: u64,
+  pub data_size: u64,
+  /// Zero when the archive has no index.
+  pub index_offset: u64,
+}
+
+impl CarV2Header {
+  /// The leftmost characteristic bit marks an index of every block,
+  /// including identity hashed ones.
+  pub fn fully_indexed(&self) -> bool { self.characteristics[0] & 0x80 != 0 }
+
+  pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+    let mut bytes = [0; HEADER_LEN];
+    bytes[..16].copy_from_slice(&self.characteristics);
+    bytes[16..24].copy_from_slice(&self.data_offset.to_le_bytes());
+    bytes[24..32].copy_from_slice(&self.data_size.to_le_bytes());
+    bytes[32..].copy_from_slice(&self.index_offset.to_le_bytes());
+    bytes
+  }
+
+  pub fn from_bytes(bytes: &[u8; HEADER_LEN]) -> Self {
+    let u64_at =
+      |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+    CarV2Header {
+      characteristics: bytes[..16].try_into().unwrap(),
+      data_offset: u64_at(16),
+      data_size: u64_at(24),
+      index_offset: u64_at(32),
+    }
+  }
+}
+
+/// The formats a CARv2 index can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexFormat {
+  /// Keyed by the digest of the multihash alone.
+  Sorted,
+  /// Keyed by the hash function code, then the digest.
+  MultihashSorted,
+}
+
+impl IndexFormat {
+  pub fn code(&self) -> u64 {
+    match self {
+      IndexFormat::Sorted => INDEX_SORTED,
+      IndexFormat::MultihashSorted => MULTIHASH_INDEX_SORTED,
+    }
+  }
+}
+
+/// Offsets of block sections in a CARv1 payload, by the multihash of their
+/// CID. When several sections have the same multihash the first one is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CarIndex {
+  format: IndexFormat,
+  // Keyed by hash function code, which is always 0 for `IndexSorted`
+  entries: BTreeMap<u64, BTreeMap<Vec<u8>, u64>>,
+}
+
+impl CarIndex {
+  pub fn new(format: IndexFormat) -> Self {
+    CarIndex { format, entries: BTreeMap::new() }
+  }
+
+  pub fn format(&self) -> IndexFormat { self.format }
+
+  pub fn len(&self) -> usize { self.entries.values().map(BTreeMap::len).sum() }
+
+  pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+  fn key(&self, hash: &Multihash) -> u64 {
+    match self.format {
+      IndexFormat::Sorted => 0,
+      IndexFormat::MultihashSorted => hash.code(),
+    }
+  }
+
+  pub fn insert(&mut self, hash: &Multihash, offset: u64) {
+    let key = self.key(hash);
+    let digests = self.entries.entry(key).or_default();
+    digests.entry(hash.digest().to_vec()).or_insert(offset);
+  }
+
+  /// Returns the offset of the section holding the block hashed to `hash`.
+  pub fn get(&self, hash: &Multihash) -> Option<u64> {
+    self.entries.get(&self.key(hash))?.get(hash.digest()).copied()
+  }
+
+  /// Writes the index, starting with the multicodec code of its format.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    // Both index codes fit in two bytes
+    let mut bytes = to_varint(self.format.code()).unwrap();
+    match self.format {
+      IndexFormat::Sorted => {
+        let empty = BTreeMap::new();
+        write_buckets(&mut bytes, self.entries.get(&0).unwrap_or(&empty));
+      }
+      IndexFormat::MultihashSorted => {
+        bytes.extend((self.entries.len() as u32).to_le_bytes());
+        for (code, digests) in &self.entries {
+          bytes.extend(code.to_le_bytes());
+          write_buckets(&mut bytes, digests);
+        }
+      }
+    }
+    bytes
+  }
+
+  /// Reads an index that starts at `offset` in its archive, which is only
+  /// used to report errors.
+  pub fn read_from<R: Read>(r: &mut R, offset: u64) -> Result<Self, CarError> {
+    let mut r = IndexReader { inner: r, offset };
+    let code =
+      varint_read_u64(&mut r.inner).map_err(|source| match source {
+        VarintError::Io(kind) => CarError::Io { offset, kind },
+        source => CarError::Length { offset, source },
+      })?;
+    r.offset += encoded_len(code) as u64;
+    let mut entries = BTreeMap::new();
+    let format = match code {
+      INDEX_SORTED => {
+        let digests = r.read_buckets()?;
+        if !digests.is_empty() {
+          entries.insert(0, digests);
+        }
+        IndexFormat::Sorted
+      }
+      MULTIHASH_INDEX_SORTED => {
+        let count = r.read_u32()?;
+        let mut last = None;
+        for _ in 0..count {
+          let start = r.offset;
+          let code = r.read_u64()?;
+          if last.is_some_and(|last| code <= last) {
+            return Err(r.invalid("hash codes out of order", start));
+          }
+          last = Some(code);
+          let digests = r.read_buckets()?;
+          if !digests.is_empty() {
+            entries.insert(code, digests);
+          }
+        }
+        IndexFormat::MultihashSorted
+      }
+      code => return Err(CarError::UnknownIndex(code)),
+    };
+    Ok(CarIndex { format, entries })
+  }
+}
+
+// Writes digests grouped by width, each entry followed by its offset
+fn write_buckets(bytes: &mut Vec<u8>, digests: &BTreeMap<Vec<u8>, u64>) {
+  let mut buckets: BTreeMap<usize, Vec<(&[u8], u64)>> = BTreeMap::new();
+  for (digest, offset) in digests {
+    buckets.entry(digest.len()).or_default().push((digest, *offset));
+  }
+  bytes.extend((buckets.len() as u32).to_le_bytes());
+  for (len, entries) in buckets {
+    let width = len + 8;
+    bytes.extend((width as u32).to_le_bytes());
+    bytes.extend(((width * entries.len()) as u64).to_le_bytes());
+    for (digest, offset) in entries {
+      bytes.extend(digest);
+      bytes.extend(offset.to_le_bytes());
+    }
+  }
+}
+
+struct IndexReader<'a, R> {
+  inner: &'a mut R,
+  offset: u64,
+}
+
+impl<R: Read> IndexReader<'_, R> {
+  fn invalid(&self, reason: &'static str, offset: u64) -> CarError {
+    CarError::InvalidIndex { reason, offset }
+  }
+
+  fn read_bytes(&mut self, len: u64) -> Result<Vec<u8>, CarError> {
+    let bytes = read_bytes(&mut self.inner, len, self.offset)?;
+    self.offset += len;
+    Ok(bytes)
+  }
+
+  fn read_u32(&mut self) -> Result<u32, CarError> {
+    Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+  }
+
+  fn read_u64(&mut self) -> Result<u64, CarError> {
+    Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+  }
+
+  fn read_buckets(&mut self) -> Result<BTreeMap<Vec<u8>, u64>, CarError> {
+    let mut digests = BTreeMap::new();
+    let count = self.read_u32()?;
+    for _ in 0..count {
+      let start = self.offset;
+      let width = u64::from(self.read_u32()?);
+      let len = self.read_u64()?;
+      if width <= 8 || len % width != 0 {
+        return Err(self.invalid("bad bucket width", start));
+      }
+      let entries = self.read_bytes(len)?;
+      for entry in entries.chunks(width as usize) {
+        let (digest, offset) = entry.split_at(width as usize - 8);
+        let offset = u64::from_le_bytes(offset.try_into().unwrap());
+        if digests.insert(digest.to_vec(), offset).is_some() {
+          return Err(self.invalid("duplicate digest", start));
+        }
+      }
+    }
+    Ok(digests)
+  }
+}
+
+// Reads a CARv1 payload, returning the index of its blocks and its length
+fn index_v1<R: Read>(
+  v1: R,
+  format: IndexFormat,
+) -> Result<(CarIndex, u64), Error> {
+  let mut reader = CarReader::new(v1)?;
+  let mut index = CarIndex::new(format);
+  loop {
+    let offset = reader.offset();
+    match reader.next_block()? {
+      Some(block) => index.insert(&block.cid().hash, offset),
+      None => return Ok((index, offset)),
+    }
+  }
+}
+
+/// Reads the CARv1 `v1`, checking every block, and indexes its sections.
+pub fn generate_index<R: Read>(
+  v1: R,
+  format: IndexFormat,
+) -> Result<CarIndex, Error> {
+  Ok(index_v1(v1, format)?.0)
+}
+
+/// Writes the CARv1 read from `v1` to `out` as a CARv2, followed by an index
+/// in `format`.
+pub fn wrap_v1<R: Read + Seek, W: Write>(
+  mut v1: R,
+  mut out: W,
+  format: IndexFormat,
+) -> Result<W, Error> {
+  let start = v1.stream_position().map_err(|e| io_error(e, 0))?;
+  let (index, data_size) = index_v1(&mut v1, format)?;
+  v1.seek(SeekFrom::Start(start)).map_err(|e| io_error(e, 0))?;
+  let header = CarV2Header {
+    characteristics: [0; 16],
+    data_offset: DATA_OFFSET,
+    data_size,
+    index_offset: DATA_OFFSET + data_size,
+  };
+  out.write_all(&PRAGMA).map_err(|e| io_error(e, 0))?;
+  out.write_all(&header.to_bytes()).map_err(|e| io_error(e, 11))?;
+  let copied = io::copy(&mut (&mut v1).take(data_size), &mut out)
+    .map_err(|e| io_error(e, DATA_OFFSET))?;
+  if copied < data_size {
+    return Err(
+      CarError::UnexpectedEof { offset: DATA_OFFSET + copied }.into(),
+    );
+  }
+  out
+    .write_all(&index.to_bytes())
+    .map_err(|e| io_error(e, header.index_offset))?;
+  out.flush().map_err(|e| io_error(e, header.index_offset))?;
+  Ok(out)
+}
+
+/// Reads blocks from a CARv2 in any order, seeking straight to them through
+/// the index. Archives without an index are indexed when opened.
+pub struct CarV2Reader<R> {
+  inner: R,
+  header: CarV2Header,
+  roots: Vec<Cid>,
+  index: CarIndex,
+}
+
+impl<R: Read + Seek> CarV2Reader<R> {
+  pub fn new(mut inner: R) -> Result<Self, Error> {
+    let mut pragma = [0; PRAGMA.len()];
+    inner.read_exact(&mut pragma).map_err(|e| io_error(e, 0))?;
+    if pragma != PRAGMA {
+      return Err(CarError::InvalidPragma.into());
+    }
+    let mut header = [0; HEADER_LEN];
+    inner
+      .read_exact(&mut header)
+      .map_err(|e| io_error(e, PRAGMA.len() as u64))?;
+    let header = CarV2Header::from_bytes(&header);
+    if header.data_offset < DATA_OFFSET {
+      return Err(CarError::InvalidHeader("data overlaps the header").into());
+    }
+    // The roots and index are read through the reader itself
+    let mut reader = CarV2Reader {
+      inner,
+      header,
+      roots: vec![],
+      index: CarIndex::new(IndexFormat::Sorted),
+    };
+    reader.roots = reader.blocks()?.roots().to_vec();
+    reader.index = if reader.header.index_offset == 0 {
+      index_v1(reader.data()?, IndexFormat::MultihashSorted)?.0
+    }
+    else {
+      let offset = reader.header.index_offset;
+      reader
+        .inner
+        .seek(SeekFrom::Start(offset))
+        .map_err(|e| io_error(e, offset))?;
+      CarIndex::read_from(&mut reader.inner, offset)?
+    };
+    Ok(reader)
+  }
+
+  pub fn header(&self) -> &CarV2Header { &self.header }
+
+  pub fn roots(&self) -> &[Cid] { &self.roots }
+
+  pub fn index(&self) -> &CarIndex { &self.index }
+
+  // Returns the data payload, positioned at its start
+  fn data(&mut self) -> Result<io::Take<&mut R>, CarError> {
+    let offset = self.header.data_offset;
+    self
+      .inner
+      .seek(SeekFrom::Start(offset))
+      .map_err(|e| io_error(e, offset))?;
+    Ok((&mut self.inner).take(self.header.data_size))
+  }
+
+  /// Reads the payload from the start as a CARv1.
+  pub fn blocks(&mut self) -> Result<CarReader<io::Take<&mut R>>, Error> {
+    CarReader::new(self.data()?)
+  }
+
+  /// Returns the block named by `cid`, if the archive holds it. The block
+  /// keeps the CID it was stored under, which may differ from `cid` in its
+  /// version or codec.
+  pub fn get(&mut self, cid: &Cid) -> Result<Option<Block>, Error> {
+    let offset = match self.index.get(&cid.hash) {
+      Some(offset) => offset,
+      None => return Ok(None),
+    };
+    if offset >= self.header.data_size {
+      return Err(
+        CarError::InvalidIndex { reason: "offset out of range", offset }.into(),
+      );
+    }
+    let start = self.header.data_offset + offset;
+    self.inner.seek(SeekFrom::Start(start)).map_err(|e| io_error(e, start))?;
+    let mut section = (&mut self.inner).take(self.header.data_size - offset);
+    match read_section(&mut section, offset, &CarLimits::default())? {
+      Some((block, _)) if block.cid().hash == cid.hash => Ok(Some(block)),
+      _ => Ok(None),
+    }
+  }
+
+  pub fn into_inner(self) -> R { self.inner }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::{
+    self,
+    Cursor,
+    Read,
+    Seek,
+    SeekFrom,
+  };
+
+  use crate::{
+    block::Block,
+    car::CarWriter,
+    carv2::{
+      generate_index,
+      wrap_v1,
+      CarIndex,
+      CarV2Header,
+      CarV2Reader,
+      IndexFormat,
+      PRAGMA,
+    },
+    cid::Cid,
+    codec::{
+      DagCborCodec,
+      RawCodec,
+    },
+    error::{
+      CarError,
+      Error,
+    },
+    ipld::Ipld,
+    multihash::{
+      Code,
+      Multihash,
+    },
+    test_util::hex,
+  };
+
+  // The CARv1 of the `car` tests: a DAG-CBOR root at offset 59 linking to
+  // the raw block "hello" at offset 152
+  fn basic_v1() -> (Vec<u8>, Vec<Block>) {
+    let hello =
+      Block::encode(&RawCodec, Code::Sha2_256, &Ipld::Bytes(b"hello".to_vec()))
+        .unwrap();
+    let root = Ipld::to_object(vec![
+      ("link".into(), Ipld::Link(hello.cid().clone())),
+      ("name".into(), Ipld::String("car".into())),
+    ]);
+    let root = Block::encode(&DagCborCodec, Code::Sha2_256, &root).unwrap();
+    let mut writer = CarWriter::new(vec![], vec![root.cid().clone()]).unwrap();
+    writer.write(&root).unwrap();
+    writer.write(&hello).unwrap();
+    (writer.finish().unwrap(), vec![root, hello])
+  }
+
+  // Written by hand from the CARv2 spec
+  const HEADER: &str = concat!(
+    "00000000000000000000000000000000",
+    "3300000000000000c200000000000000f500000000000000"
+  );
+  const INDEX_SORTED: &str = concat!(
+    "800801000000280000005000000000000000",
+    "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+    "9800000000000000",
+    "a76ef08162b66e765ba38cd954a31c54dfb7f38e444d9dc7273160e25c7391d0",
+    "3b00000000000000"
+  );
+  const MULTIHASH_INDEX_SORTED: &str = concat!(
+    "81080100000012000000000000000100000028000000500000000000000",
+    "02cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b982",
+    "49800000000000000",
+    "a76ef08162b66e765ba38cd954a31c54dfb7f38e444d9dc7273160e25c7391d0",
+    "3b00000000000000"
+  );
+
+  #[test]
+  fn wrap_fixture() {
+    let (v1, blocks) = basic_v1();
+    for (format, index) in [
+      (IndexFormat::Sorted, INDEX_SORTED),
+      (IndexFormat::MultihashSorted, MULTIHASH_INDEX_SORTED),
+    ] {
+      let v2 = wrap_v1(Cursor::new(&v1), vec![], format).unwrap();
+      let mut expected = PRAGMA.to_vec();
+      expected.extend(hex(HEADER));
+      expected.extend(&v1);
+      expected.extend(hex(index));
+      assert_eq!(v2, expected);
+
+      let mut reader = CarV2Reader::new(Cursor::new(v2)).unwrap();
+      assert_eq!(reader.roots(), [blocks[0].cid().clone()]);
+      assert_eq!(reader.index().format(), format);
+      assert!(!reader.header().fully_indexed());
+      // Random access, in any order
+      for block in blocks.iter().rev() {
+        assert_eq!(reader.get(block.cid()).unwrap().as_ref(), Some(block));
+      }
+      let all = reader.blocks().unwrap().collect::<Result<Vec<_>, _>>();
+      assert_eq!(all.unwrap(), blocks);
+    }
+  }
+
+  // The carv2-basic fixture of the CARv2 spec, written by go-car: DAG-PB
+  // nodes over the raw blocks "fish" and "lobster", with an `IndexSorted`
+  #[test]
+  fn carv2_basic() {
+    let bytes = include_bytes!("../tests/fixtures/carv2-basic.car");
+    assert_eq!(bytes[..PRAGMA.len()], PRAGMA);
+    let mut reader = CarV2Reader::new(Cursor::new(&bytes[..])).unwrap();
+    assert_eq!(*reader.header(), CarV2Header {
+      characteristics: [0; 16],
+      data_offset: 51,
+      data_size: 448,
+      index_offset: 499,
+    });
+    let cids: Vec<Cid> = [
+      "QmfEoLyB5NndqeKieExd1rtJzTduQUPEV8TwAYcUiy3H5Z",
+      "QmczfirA7VEH7YVvKPTPoU69XM3qY4DC39nnTsWd4K3SkM",
+      "Qmcpz2FHJD7VAhg1fxFXdYJKePtkx1BsHuCrAgWVnaHMTE",
+      "bafkreifuosuzujyf4i6psbneqtwg2fhplc2wxptc5euspa2gn3bwhnihfu",
+      "bafkreifc4hca3inognou377hfhvu2xfchn2ltzi7yu27jkaeujqqqdbjju",
+    ]
+    .iter()
+    .map(|s| s.parse().unwrap())
+    .collect();
+    assert_eq!(reader.roots(), &cids[..1]);
+    assert_eq!(reader.index().format(), IndexFormat::Sorted);
+    assert_eq!(reader.index().len(), 5);
+    // Sections start after the 57 byte CARv1 header
+    let offsets = [57, 139, 274, 363, 404];
+    for (cid, offset) in cids.iter().zip(offsets).rev() {
+      assert_eq!(reader.index().get(&cid.hash), Some(offset));
+      assert_eq!(reader.get(cid).unwrap().unwrap().cid(), cid);
+    }
+    assert_eq!(reader.get(&cids[3]).unwrap().unwrap().data(), b"fish");
+    assert_eq!(reader.get(&cids[4]).unwrap().unwrap().data(), b"lobster");
+    let blocks = reader.blocks().unwrap().collect::<Result<Vec<_>, _>>();
+    let read: Vec<Cid> =
+      blocks.unwrap().iter().map(|block| block.cid().clone()).collect();
+    assert_eq!(read, cids);
+
+    // Wrapping the payload again gives back the same archive
+    let v1 = Cursor::new(&bytes[51..499]);
+    assert_eq!(wrap_v1(v1, vec![], IndexFormat::Sorted).unwrap(), bytes);
+  }
+
+  #[test]
+  fn lookups() {
+    let (v1, blocks) = basic_v1();
+    let v2 = wrap_v1(Cursor::new(&v1), vec![], IndexFormat::Sorted).unwrap();
+    let mut reader = CarV2Reader::new(Cursor::new(v2)).unwrap();
+    let missing = Cid::new_v1(0x55, Multihash::hash(Code::Sha2_256, b"x"));
+    assert_eq!(reader.get(&missing).unwrap(), None);
+    // Found by multihash, but returned under the CID it was stored with
+    let raw = Cid::new_v1(0x55, blocks[0].cid().hash.clone());
+    assert_eq!(reader.get(&raw).unwrap().as_ref(), Some(&blocks[0]));
+    // `IndexSorted` ignores the hash function, the section does not
+    let digest = blocks[1].cid().hash.digest().to_vec();
+    let mut bytes = vec![Code::Sha3_256.code() as u8, 32];
+    bytes.extend(digest);
+    let other = Multihash::from_bytes(&mut &bytes[..]).unwrap();
+    assert_eq!(reader.get(&Cid::new_v1(0x55, other)).unwrap(), None);
+  }
+
+  #[test]
+  fn unindexed() {
+    // A CARv2 without an index is indexed when opened
+    let (v1, blocks) = basic_v1();
+    let mut v2 = PRAGMA.to_vec();
+    let header = CarV2Header {
+      characteristics: [0; 16],
+      data_offset: 51,
+      data_size: v1.len() as u64,
+      index_offset: 0,
+    };
+    v2.extend(header.to_bytes());
+    v2.extend(&v1);
+    let mut reader = CarV2Reader::new(Cursor::new(v2)).unwrap();
+    assert_eq!(reader.index().len(), 2);
+    assert_eq!(reader.get(blocks[1].cid()).unwrap().as_ref(), Some(&blocks[1]));
+  }
+
+  #[test]
+  fn index_roundtrip() {
+    let (v1, blocks) = basic_v1();
+    for format in [IndexFormat::Sorted, IndexFormat::MultihashSorted] {
+      let mut index = generate_index(&v1[..], format).unwrap();
+      assert_eq!(index.get(&blocks[0].cid().hash), Some(59));
+      assert_eq!(index.get(&blocks[1].cid().hash), Some(152));
+      // Digests of several widths and hash functions
+      index.insert(&Multihash::hash(Code::Blake2b512, b"a"), 200);
+      index.insert(&Multihash::hash(Code::Sha3_256, b"b"), 300);
+      index.insert(&Multihash::hash(Code::Sha3_256, b"b"), 400);
+      assert_eq!(index.len(), 4);
+      let bytes = index.to_bytes();
+      assert_eq!(CarIndex::read_from(&mut &bytes[..], 0), Ok(index));
+    }
+    let empty = CarIndex::new(IndexFormat::Sorted);
+    assert_eq!(empty.to_bytes(), [0x80, 0x08, 0, 0, 0, 0]);
+    assert_eq!(CarIndex::read_from(&mut &empty.to_bytes()[..], 0), Ok(empty));
+  }
+
+  #[test]
+  fn header_bytes() {
+    let mut bytes = hex(HEADER);
+    bytes[0] = 0x80;
+    let header = CarV2Header::from_bytes(&bytes[..].try_into().unwrap());
+    assert!(header.fully_indexed());
+    assert_eq!(
+      (header.data_offset, header.data_size, header.index_offset),
+      (51, 194, 245)
+    );
+    assert_eq!(header.to_bytes()[..], bytes);
+  }
+
+  // A file that loses its last bytes when rewound, as if truncated between
+  // the indexing and the copying passes of `wrap_v1`
+  struct Shrinking(Cursor<Vec<u8>>);
+
+  impl Read for Shrinking {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.0.read(buf) }
+  }
+
+  impl Seek for Shrinking {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+      if let SeekFrom::Start(_) = pos {
+        let len = self.0.get_ref().len();
+        self.0.get_mut().truncate(len - 10);
+      }
+      self.0.seek(pos)
+    }
+  }
+
+  #[test]
+  fn wrap_shrinking() {
+    let (v1, _) = basic_v1();
+    let len = v1.len() as u64;
+    let shrinking = Shrinking(Cursor::new(v1));
+    // The copy stops 10 bytes short, past the pragma and header
+    assert_eq!(
+      wrap_v1(shrinking, vec![], IndexFormat::Sorted).err(),
+      Some(Error::Car(CarError::UnexpectedEof { offset: 51 + len - 10 }))
+    );
+  }
+
+  #[test]
+  fn malformed() {
+    let (v1, _) = basic_v1();
+    let err =
+      |bytes: Vec<u8>| CarV2Reader::new(Cursor::new(bytes)).err().unwrap();
+    assert_eq!(err(v1.clone()), Error::Car(CarError::InvalidPragma));
+    let v2 = wrap_v1(Cursor::new(&v1), vec![], IndexFormat::Sorted).unwrap();
+
+    let mut overlap = v2.clone();
+    overlap[27] = 50;
+    assert_eq!(
+      err(overlap),
+      Error::Car(CarError::InvalidHeader("data overlaps the header"))
+    );
+
+    let mut unknown = v2.clone();
+    unknown[245] = 0x82;
+    assert_eq!(err(unknown), Error::Car(CarError::UnknownIndex(0x0402)));
+
+    let mut width = v2.clone();
+    width[251] = 41;
+    assert_eq!(
+      err(width),
+      Error::Car(CarError::InvalidIndex {
+        reason: "bad bucket width",
+        offset: 251
+      })
+    );
+
+    let mut truncated = v2.clone();
+    truncated.pop();
+    assert_eq!(
+      err(truncated),
+      Error::Car(CarError::UnexpectedEof { offset: 342 })
+    );
+
+    // An index pointing past the payload, the root's entry comes last
+    let mut past = v2;
+    let last = past.len() - 8;
+    past[last] = 0xff;
+    let mut reader = CarV2Reader::new(Cursor::new(past)).unwrap();
+    let root = reader.roots()[0].clone();
+    assert_eq!(
+      reader.get(&root),
+      Err(Error::Car(CarError::InvalidIndex {
+        reason: "offset out of range",
+        offset: 0xff
+      }))
+    );
+  }
+}
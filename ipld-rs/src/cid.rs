@@ -1,11 +1,19 @@
 use crate::{
-  multihash::Multihash,
+  codec::DAG_PB,
+  error::CidError,
+  multibase::{
+    self,
+    Base,
+  },
+  multihash::{
+    Code,
+    Multihash,
+  },
   unsigned_varint::{
     to_varint,
     varint_read_u64,
   },
 };
-use anyhow::Result;
 use serde::{
   de,
   ser,
@@ -16,13 +24,15 @@ use std::{
   convert::TryFrom,
   fmt,
   io::Read,
+  str::FromStr,
 };
 
-/// Supported CID spec:
-/// dag-cbor codec
-/// SHA-256 or Keccak
-
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// A content identifier: a multihash of a block, tagged with the multicodec
+/// of the block's encoding.
+///
+/// CIDv1 may use any codec and any multihash. CIDv0 is always a dag-pb block
+/// hashed with sha2-256, written as the bare multihash.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct Cid {
   pub version: u64,
   pub codec: u64,
@@ -34,38 +44,117 @@ impl Cid {
     Self { version, codec, hash }
   }
 
-  pub fn to_bytes(&self) -> Vec<u8> {
-    let mut bytes = vec![];
-    bytes.extend(to_varint(self.version));
-    bytes.extend(to_varint(self.codec));
+  /// Builds a CIDv0, which must be the sha2-256 hash of a dag-pb block.
+  pub fn new_v0(hash: Multihash) -> Result<Self, CidError> {
+    if hash.code() != Code::Sha2_256.code() || hash.size() != 32 {
+      return Err(CidError::InvalidV0);
+    }
+    Ok(Self { version: 0, codec: DAG_PB, hash })
+  }
+
+  pub fn new_v1(codec: u64, hash: Multihash) -> Self {
+    Self { version: 1, codec, hash }
+  }
+
+  /// Returns the CIDv1 naming the same block.
+  pub fn into_v1(self) -> Self { Self { version: 1, ..self } }
+
+  /// Returns the CIDv0 naming the same block, if it can be expressed as one.
+  pub fn try_into_v0(self) -> Result<Self, CidError> {
+    if self.codec != DAG_PB {
+      return Err(CidError::InvalidV0);
+    }
+    Self::new_v0(self.hash)
+  }
+
+  /// CIDv0 is just the multihash, CIDv1 prefixes it with the version and
+  /// codec. Fails if the version or codec is too large for a varint.
+  pub fn to_bytes(&self) -> Result<Vec<u8>, CidError> {
+    if self.version == 0 {
+      return Ok(self.hash.to_bytes());
+    }
+    let mut bytes = to_varint(self.version).map_err(CidError::Version)?;
+    bytes.extend(to_varint(self.codec).map_err(CidError::Codec)?);
     bytes.extend(self.hash.to_bytes());
-    bytes
+    Ok(bytes)
   }
 
-  pub fn from_bytes<R: Read>(r: &mut R) -> Result<Cid> {
-    let version = varint_read_u64(r).unwrap();
-    let codec = varint_read_u64(r).unwrap();
-    let hash = Multihash::from_bytes(r).unwrap();
+  pub fn from_bytes<R: Read>(r: &mut R) -> Result<Cid, CidError> {
+    let version = varint_read_u64(r).map_err(CidError::Version)?;
+    // A CIDv0 starts with the sha2-256 code of its multihash instead
+    if version == Code::Sha2_256.code() {
+      let hash = Multihash::from_bytes(&mut [version as u8].chain(r))?;
+      return Cid::new_v0(hash);
+    }
+    if version != 1 {
+      return Err(CidError::UnsupportedVersion(version));
+    }
+    let codec = varint_read_u64(r).map_err(CidError::Codec)?;
+    let hash = Multihash::from_bytes(r)?;
     Ok(Cid { version, codec, hash })
   }
+
+  /// Encodes the CID bytes in `base`, prefixed with its multibase code.
+  pub fn to_string_of_base(&self, base: Base) -> Result<String, CidError> {
+    Ok(base.encode(&self.to_bytes()?))
+  }
+
+  /// The string written by `Display`: CIDv1 in base32, and CIDv0 as its bare
+  /// base58btc multihash without a multibase code.
+  pub fn try_to_string(&self) -> Result<String, CidError> {
+    if self.version == 0 {
+      let string = Base::Base58Btc.encode(&self.hash.to_bytes());
+      return Ok(string[1..].to_string());
+    }
+    self.to_string_of_base(Base::Base32)
+  }
 }
 
+/// Writes [`Cid::try_to_string`]. The CIDs that [`Cid::to_bytes`] rejects have
+/// no string form, so they are written as `version/codec/hash` instead, with
+/// the multihash in base16, rather than failing inside error messages.
 impl fmt::Display for Cid {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "{}, {}, {}", self.version, self.codec, self.hash)
+    match self.try_to_string() {
+      Ok(string) => f.write_str(&string),
+      Err(_) => {
+        let hash = Base::Base16.encode(&self.hash.to_bytes());
+        write!(f, "{}/{}/{}", self.version, self.codec, hash)
+      }
+    }
+  }
+}
+
+/// Parses a multibase encoded CID, in any base known to [`Base`], or a bare
+/// base58btc CIDv0.
+impl FromStr for Cid {
+  type Err = CidError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    if s.len() == 46 && s.starts_with("Qm") {
+      let bytes = Base::Base58Btc.decode(&format!("z{}", s))?;
+      return Cid::try_from(bytes);
+    }
+    let (_, bytes) = multibase::decode(s)?;
+    let mut rest = &bytes[..];
+    let cid = Cid::from_bytes(&mut rest)?;
+    if !rest.is_empty() {
+      return Err(CidError::TrailingBytes);
+    }
+    Ok(cid)
   }
 }
 
 impl ser::Serialize for Cid {
   fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
   where S: ser::Serializer {
-    let value = ByteBuf::from(self.to_bytes());
+    let value = ByteBuf::from(self.to_bytes().map_err(ser::Error::custom)?);
     serializer.serialize_newtype_struct(CID_SERDE_PRIVATE_IDENTIFIER, &value)
   }
 }
 
 impl TryFrom<Vec<u8>> for Cid {
-  type Error = anyhow::Error;
+  type Error = CidError;
 
   fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
     Self::from_bytes(&mut &bytes[..])
@@ -73,7 +162,7 @@ impl TryFrom<Vec<u8>> for Cid {
 }
 
 impl TryFrom<&[u8]> for Cid {
-  type Error = anyhow::Error;
+  type Error = CidError;
 
   fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
     Self::from_bytes(&mut &bytes[..])
@@ -117,7 +206,17 @@ impl<'de> de::Visitor<'de> for BytesToCidVisitor {
 mod tests {
   use crate::{
     cid::Cid,
-    multihash::Multihash,
+    error::{
+      CidError,
+      MultibaseError,
+      MultihashError,
+      VarintError,
+    },
+    multibase::Base,
+    multihash::{
+      Code,
+      Multihash,
+    },
   };
 
   #[test]
@@ -125,6 +224,183 @@ mod tests {
     let data = vec![1];
     let digest = Multihash::sha3_256(&data);
     let cid = Cid { version: 0x01, codec: 0x71, hash: digest };
-    assert_eq!(cid, Cid::from_bytes(&mut &cid.to_bytes()[..]).unwrap());
+    let bytes = cid.to_bytes().unwrap();
+    assert_eq!(cid, Cid::from_bytes(&mut &bytes[..]).unwrap());
+  }
+
+  #[test]
+  fn cid_malformed() {
+    let cid = Cid::new(1, 0x71, Multihash::sha3_256(&[1]));
+    let bytes = cid.to_bytes().unwrap();
+    assert_eq!(
+      Cid::try_from(&bytes[..0]),
+      Err(CidError::Version(VarintError::UnexpectedEof { offset: 0 }))
+    );
+    assert_eq!(
+      Cid::try_from(&bytes[..1]),
+      Err(CidError::Codec(VarintError::UnexpectedEof { offset: 0 }))
+    );
+    assert_eq!(
+      Cid::try_from(&bytes[..bytes.len() - 1]),
+      Err(CidError::Multihash(MultihashError::Truncated {
+        expected: 32,
+        found: 31
+      }))
+    );
+    // Codecs and versions from 2^63 could not be decoded again
+    let codec = Cid::new(1, 1 << 63, cid.hash.clone());
+    assert_eq!(
+      codec.to_bytes(),
+      Err(CidError::Codec(VarintError::TooLarge(1 << 63)))
+    );
+    assert_eq!(
+      codec.try_to_string(),
+      Err(CidError::Codec(VarintError::TooLarge(1 << 63)))
+    );
+    assert_eq!(
+      codec.to_string(),
+      format!("1/{}/{}", 1u64 << 63, Base::Base16.encode(&cid.hash.to_bytes()))
+    );
+    let version = Cid::new(u64::MAX, 0x71, cid.hash);
+    assert_eq!(
+      version.to_bytes(),
+      Err(CidError::Version(VarintError::TooLarge(u64::MAX)))
+    );
+  }
+
+  #[test]
+  fn cid_strings() {
+    // The CIDv1 and CIDv0 forms of the same dag-pb block, from the IPFS docs
+    let v1 = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+    let cid: Cid = v1.parse().unwrap();
+    assert_eq!((cid.version, cid.codec, cid.hash.code()), (1, 0x70, 0x12));
+    assert_eq!(cid.to_string(), v1);
+    assert_eq!(cid.to_string_of_base(Base::Base32).unwrap(), v1);
+    let v0 = Cid::new_v0(cid.hash.clone()).unwrap();
+    assert_eq!(
+      v0.to_string(),
+      "QmbWqxBEKC3P8tqsKc98xmWNzrzDtRLMiMPL8wBuTGsMnR"
+    );
+    for base in Base::ALL {
+      let s = cid.to_string_of_base(base).unwrap();
+      assert!(s.starts_with(base.code()));
+      assert_eq!(s.parse::<Cid>().unwrap(), cid);
+    }
+    assert_eq!(
+      "BAFYBEIGDYRZT5SFP7UDM7HU76UH7Y26NF3EFUYLQABF3OCLGTQY55FBZDI"
+        .parse::<Cid>()
+        .unwrap(),
+      cid
+    );
+    assert_eq!(
+      "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi"
+        .to_uppercase()
+        .replacen('B', "b", 1)
+        .parse::<Cid>()
+        .unwrap(),
+      cid
+    );
+  }
+
+  #[test]
+  fn cid_strings_malformed() {
+    assert_eq!(
+      "".parse::<Cid>(),
+      Err(CidError::Multibase(MultibaseError::Empty))
+    );
+    assert_eq!(
+      "?abc".parse::<Cid>(),
+      Err(CidError::Multibase(MultibaseError::UnknownCode('?')))
+    );
+    assert_eq!(
+      "bafy1".parse::<Cid>(),
+      Err(CidError::Multibase(MultibaseError::Digit { digit: '1', offset: 4 }))
+    );
+    // Same bytes as the `v1` string, but with a non-zero unused bit
+    assert_eq!(
+      "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdj"
+        .parse::<Cid>(),
+      Err(CidError::Multibase(MultibaseError::TrailingBits { offset: 58 }))
+    );
+    // Padding would give the same CID a second string
+    assert_eq!(
+      "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi="
+        .parse::<Cid>(),
+      Err(CidError::Multibase(MultibaseError::Digit {
+        digit: '=',
+        offset: 59
+      }))
+    );
+    let cid = Cid::new(1, 0x71, Multihash::sha3_256(&[1]));
+    let mut bytes = cid.to_bytes().unwrap();
+    bytes.push(0);
+    assert_eq!(
+      Base::Base32.encode(&bytes).parse::<Cid>(),
+      Err(CidError::TrailingBytes)
+    );
+  }
+
+  #[test]
+  fn cid_v0() {
+    let string = "QmbWqxBEKC3P8tqsKc98xmWNzrzDtRLMiMPL8wBuTGsMnR";
+    let v0: Cid = string.parse().unwrap();
+    assert_eq!((v0.version, v0.codec, v0.hash.code()), (0, 0x70, 0x12));
+    assert_eq!(v0.to_string(), string);
+    assert_eq!(v0.to_bytes(), Ok(v0.hash.to_bytes()));
+    assert_eq!(v0.to_bytes().unwrap().len(), 34);
+    assert_eq!(Cid::try_from(v0.to_bytes().unwrap()).unwrap(), v0);
+
+    let v1 = v0.clone().into_v1();
+    assert_eq!(
+      v1.to_string(),
+      "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi"
+    );
+    assert_eq!(v1.clone().try_into_v0().unwrap(), v0);
+    assert_eq!(v0.clone().into_v1(), v1);
+    assert_eq!(v0.clone().try_into_v0().unwrap(), v0);
+
+    let cbor = Cid::new_v1(0x71, v0.hash.clone());
+    assert_eq!(cbor.try_into_v0(), Err(CidError::InvalidV0));
+    let sha3 = Cid::new_v1(0x70, Multihash::sha3_256(&[1]));
+    assert_eq!(sha3.clone().try_into_v0(), Err(CidError::InvalidV0));
+    assert_eq!(Cid::new_v0(sha3.hash), Err(CidError::InvalidV0));
+  }
+
+  #[test]
+  fn cid_v0_malformed() {
+    // A sha2-256 multihash with a 16 byte digest
+    let mut short = vec![0x12, 0x10];
+    short.extend([0; 16]);
+    assert_eq!(Cid::try_from(short), Err(CidError::InvalidV0));
+    assert_eq!(
+      Cid::try_from(&[0x12, 0x20, 0][..]),
+      Err(CidError::Multihash(MultihashError::Truncated {
+        expected: 32,
+        found: 1
+      }))
+    );
+    // Version 0 is never written out explicitly
+    assert_eq!(
+      Cid::try_from(&[0x00, 0x70, 0x12, 0x00][..]),
+      Err(CidError::UnsupportedVersion(0))
+    );
+    assert_eq!(
+      Cid::try_from(&[0x02, 0x71, 0x12, 0x00][..]),
+      Err(CidError::UnsupportedVersion(2))
+    );
+    assert_eq!(
+      "Qm0WqxBEKC3P8tqsKc98xmWNzrzDtRLMiMPL8wBuTGsMnR".parse::<Cid>(),
+      Err(CidError::Multibase(MultibaseError::Digit { digit: '0', offset: 3 }))
+    );
+  }
+
+  #[test]
+  fn verify_published_cid() {
+    // The raw block with no content, as added by `ipfs add --cid-version 1`
+    let cid: Cid =
+      "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku"
+        .parse()
+        .unwrap();
+    assert_eq!(cid, Cid::new_v1(0x55, Multihash::hash(Code::Sha2_256, b"")));
   }
 }
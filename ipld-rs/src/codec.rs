@@ -0,0 +1,258 @@
+//! Codecs identified by their multicodec code, and a registry that picks the
+//! right one for a block from the `codec` field of its CID.
+
+use std::collections::BTreeMap;
+
+use crate::{
+  cid::Cid,
+  dag_cbor,
+  dag_json,
+  dag_pb,
+  error::{
+    CodecError,
+    DagCborError,
+    Error,
+  },
+  ipld::Ipld,
+};
+
+/// Multicodec code of raw binary blocks.
+pub const RAW: u64 = 0x55;
+/// Multicodec code of DAG-PB.
+pub const DAG_PB: u64 = 0x70;
+/// Multicodec code of DAG-CBOR.
+pub const DAG_CBOR: u64 = 0x71;
+/// Multicodec code of DAG-JSON.
+pub const DAG_JSON: u64 = 0x0129;
+
+/// Conversion between [`Ipld`] and the bytes of a block.
+pub trait Codec: Send + Sync {
+  /// The multicodec code identifying this codec in CIDs.
+  fn code(&self) -> u64;
+
+  fn encode(&self, ipld: &Ipld) -> Result<Vec<u8>, Error>;
+
+  /// Decodes a whole block, failing if any bytes are left over.
+  fn decode(&self, bytes: &[u8]) -> Result<Ipld, Error>;
+}
+
+/// Raw binary data, which maps to and from [`Ipld::Bytes`] as is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RawCodec;
+
+impl Codec for RawCodec {
+  fn code(&self) -> u64 { RAW }
+
+  fn encode(&self, ipld: &Ipld) -> Result<Vec<u8>, Error> {
+    match ipld {
+      Ipld::Bytes(bytes) => Ok(bytes.clone()),
+      _ => Err(CodecError::NotBytes.into()),
+    }
+  }
+
+  fn decode(&self, bytes: &[u8]) -> Result<Ipld, Error> {
+    Ok(Ipld::Bytes(bytes.to_vec()))
+  }
+}
+
+/// DAG-CBOR, decoded in the default lenient mode.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DagCborCodec;
+
+impl Codec for DagCborCodec {
+  fn code(&self) -> u64 { DAG_CBOR }
+
+  fn encode(&self, ipld: &Ipld) -> Result<Vec<u8>, Error> {
+    Ok(dag_cbor::serialize(ipld)?)
+  }
+
+  fn decode(&self, bytes: &[u8]) -> Result<Ipld, Error> {
+    let mut rest = bytes;
+    let ipld = dag_cbor::deserialize(&mut rest)?;
+    if !rest.is_empty() {
+      let offset = (bytes.len() - rest.len()) as u64;
+      return Err(DagCborError::TrailingBytes { offset }.into());
+    }
+    Ok(ipld)
+  }
+}
+
+/// DAG-JSON.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DagJsonCodec;
+
+impl Codec for DagJsonCodec {
+  fn code(&self) -> u64 { DAG_JSON }
+
+  fn encode(&self, ipld: &Ipld) -> Result<Vec<u8>, Error> {
+    Ok(dag_json::serialize(ipld)?)
+  }
+
+  fn decode(&self, mut bytes: &[u8]) -> Result<Ipld, Error> {
+    Ok(dag_json::deserialize(&mut bytes)?)
+  }
+}
+
+/// DAG-PB.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DagPbCodec;
+
+impl Codec for DagPbCodec {
+  fn code(&self) -> u64 { DAG_PB }
+
+  fn encode(&self, ipld: &Ipld) -> Result<Vec<u8>, Error> {
+    Ok(dag_pb::serialize(ipld)?)
+  }
+
+  fn decode(&self, mut bytes: &[u8]) -> Result<Ipld, Error> {
+    Ok(dag_pb::deserialize(&mut bytes)?)
+  }
+}
+
+/// Codecs keyed by multicodec code. The default registry contains every codec
+/// implemented by the crate; [`Registry::new`] starts out empty.
+pub struct Registry {
+  codecs: BTreeMap<u64, Box<dyn Codec>>,
+}
+
+impl Registry {
+  pub fn new() -> Self { Registry { codecs: BTreeMap::new() } }
+
+  /// Adds `codec` under its own code, returning the codec it replaces.
+  pub fn register<C: Codec + 'static>(
+    &mut self,
+    codec: C,
+  ) -> Option<Box<dyn Codec>> {
+    self.codecs.insert(codec.code(), Box::new(codec))
+  }
+
+  pub fn get(&self, code: u64) -> Option<&dyn Codec> {
+    self.codecs.get(&code).map(|codec| codec.as_ref())
+  }
+
+  fn codec(&self, code: u64) -> Result<&dyn Codec, Error> {
+    self.get(code).ok_or_else(|| CodecError::UnknownCodec(code).into())
+  }
+
+  /// Encodes `ipld` with the codec registered under `code`.
+  pub fn encode(&self, code: u64, ipld: &Ipld) -> Result<Vec<u8>, Error> {
+    self.codec(code)?.encode(ipld)
+  }
+
+  /// Decodes the block `bytes` with the codec named by its CID.
+  pub fn decode(&self, cid: &Cid, bytes: &[u8]) -> Result<Ipld, Error> {
+    self.codec(cid.codec)?.decode(bytes)
+  }
+}
+
+impl Default for Registry {
+  fn default() -> Self {
+    let mut registry = Registry::new();
+    registry.register(RawCodec);
+    registry.register(DagPbCodec);
+    registry.register(DagCborCodec);
+    registry.register(DagJsonCodec);
+    registry
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    cid::Cid,
+    codec::{
+      Codec,
+      Registry,
+      DAG_CBOR,
+      DAG_JSON,
+      DAG_PB,
+      RAW,
+    },
+    error::{
+      CodecError,
+      DagCborError,
+      Error,
+    },
+    ipld::Ipld,
+    multihash::Multihash,
+  };
+
+  // Stores strings as their UTF-8 bytes
+  struct Utf8Codec;
+
+  impl Codec for Utf8Codec {
+    fn code(&self) -> u64 { 0x300001 }
+
+    fn encode(&self, ipld: &Ipld) -> Result<Vec<u8>, Error> {
+      match ipld {
+        Ipld::String(s) => Ok(s.as_bytes().to_vec()),
+        _ => Err(CodecError::Custom("not a string".into()).into()),
+      }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Ipld, Error> {
+      String::from_utf8(bytes.to_vec())
+        .map(Ipld::String)
+        .map_err(|e| CodecError::Custom(e.to_string()).into())
+    }
+  }
+
+  fn cid(codec: u64, bytes: &[u8]) -> Cid {
+    Cid::new(1, codec, Multihash::sha3_256(bytes))
+  }
+
+  #[test]
+  fn dispatch_on_cid_codec() {
+    let registry = Registry::default();
+    let map = Ipld::to_object(vec![("a".into(), Ipld::Integer(1))]);
+    let node = Ipld::to_object(vec![
+      ("Data".into(), Ipld::Bytes(vec![8, 1])),
+      ("Links".into(), Ipld::Array(vec![])),
+    ]);
+    let cases = [
+      (RAW, Ipld::Bytes(vec![1, 2, 3]), vec![1, 2, 3]),
+      (DAG_CBOR, map.clone(), vec![0xa1, 0x61, 0x61, 0x01]),
+      (DAG_JSON, map, br#"{"a":1}"#.to_vec()),
+      (DAG_PB, node, vec![0x0a, 0x02, 0x08, 0x01]),
+    ];
+    for (code, ipld, bytes) in cases {
+      assert_eq!(registry.get(code).unwrap().code(), code);
+      assert_eq!(registry.encode(code, &ipld).unwrap(), bytes);
+      assert_eq!(registry.decode(&cid(code, &bytes), &bytes).unwrap(), ipld);
+    }
+  }
+
+  #[test]
+  fn registry_errors() {
+    let registry = Registry::default();
+    assert_eq!(
+      registry.decode(&cid(0x300001, b"x"), b"x"),
+      Err(Error::Codec(CodecError::UnknownCodec(0x300001)))
+    );
+    assert_eq!(
+      registry.encode(RAW, &Ipld::Null),
+      Err(Error::Codec(CodecError::NotBytes))
+    );
+    assert_eq!(
+      registry.decode(&cid(DAG_CBOR, &[0xf6, 0xf6]), &[0xf6, 0xf6]),
+      Err(Error::DagCbor(DagCborError::TrailingBytes { offset: 1 }))
+    );
+    assert!(Registry::new().get(DAG_CBOR).is_none());
+  }
+
+  #[test]
+  fn user_codecs() {
+    let mut registry = Registry::default();
+    assert!(registry.register(Utf8Codec).is_none());
+    let ipld = Ipld::String("hello".into());
+    let bytes = registry.encode(0x300001, &ipld).unwrap();
+    assert_eq!(bytes, b"hello");
+    assert_eq!(registry.decode(&cid(0x300001, &bytes), &bytes).unwrap(), ipld);
+    assert_eq!(
+      registry.encode(0x300001, &Ipld::Null),
+      Err(Error::Codec(CodecError::Custom("not a string".into())))
+    );
+    // Registering under an existing code replaces the previous codec
+    assert_eq!(registry.register(Utf8Codec).unwrap().code(), 0x300001);
+  }
+}
@@ -1,262 +1,756 @@
-use anyhow::Result;
 use std::{
+  cmp::Ordering,
   collections::BTreeMap,
-  io::Read,
+  io::{
+    self,
+    Read,
+    Write,
+  },
+  mem,
 };
-use thiserror::Error;
 
 use crate::{
   cid::Cid,
+  error::{
+    CidError,
+    DagCborError::{
+      self,
+      *,
+    },
+  },
   ipld::Ipld,
 };
 
-pub fn serialize(ipld: &Ipld) -> Vec<u8> {
+type Result<T> = std::result::Result<T, DagCborError>;
+
+/// Encodes `ipld` into a buffer allocated up front with its exact encoded
+/// length.
+pub fn serialize(ipld: &Ipld) -> Result<Vec<u8>> {
+  let mut buf = Vec::with_capacity(encoded_len(ipld)? as usize);
+  serialize_into(ipld, &mut buf)?;
+  Ok(buf)
+}
+
+/// Encodes `ipld` straight into `w`, without any intermediate buffers.
+pub fn serialize_into<W: Write>(ipld: &Ipld, w: &mut W) -> Result<()> {
+  ser_ipld(&mut Writer { inner: w, offset: 0 }, ipld)
+}
+
+/// Wraps the output and counts the bytes written so far, so that errors can
+/// point at the offset where writing failed.
+struct Writer<'a, W> {
+  inner: &'a mut W,
+  offset: u64,
+}
+
+impl<'a, W: Write> Writer<'a, W> {
+  fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+    let offset = self.offset;
+    self.inner.write_all(buf).map_err(|e| Io { offset, kind: e.kind() })?;
+    self.offset += buf.len() as u64;
+    Ok(())
+  }
+}
+
+fn ser_ipld<W: Write>(w: &mut Writer<W>, ipld: &Ipld) -> Result<()> {
   match ipld {
-    Ipld::Null => ser_null(),
-    Ipld::Bool(b) => ser_bool(b),
-    Ipld::Number(i) => ser_u64(0, *i),
-    Ipld::String(s) => ser_string(s),
-    Ipld::Bytes(b) => ser_bytes(b),
-    Ipld::Array(a) => ser_array(a),
-    Ipld::Object(o) => ser_object(o),
-    Ipld::Link(c) => ser_link(c),
+    Ipld::Null => ser_null(w),
+    Ipld::Bool(b) => ser_bool(w, b),
+    Ipld::Integer(i) => ser_integer(w, *i),
+    Ipld::Float(f) => ser_float(w, *f),
+    Ipld::String(s) => ser_string(w, s),
+    Ipld::Bytes(b) => ser_bytes(w, b),
+    Ipld::Array(a) => ser_array(w, a),
+    Ipld::Object(o) => ser_object(w, o),
+    Ipld::Link(c) => ser_link(w, c),
   }
 }
 
-fn ser_null() -> Vec<u8> { vec![0xf6] }
+fn ser_null<W: Write>(w: &mut Writer<W>) -> Result<()> { w.write_all(&[0xf6]) }
 
-fn ser_bool(b: &bool) -> Vec<u8> {
+fn ser_bool<W: Write>(w: &mut Writer<W>, b: &bool) -> Result<()> {
   match b {
-    true => vec![0xf5],
-    false => vec![0xf4],
+    true => w.write_all(&[0xf5]),
+    false => w.write_all(&[0xf4]),
   }
 }
 
-fn ser_u8(major: u8, n: u8) -> Vec<u8> {
-  if n <= 0x17 { vec![(major << 5) | n] } else { vec![(major << 5) | 24, n] }
+fn ser_u8<W: Write>(w: &mut Writer<W>, major: u8, n: u8) -> Result<()> {
+  if n <= 0x17 {
+    w.write_all(&[(major << 5) | n])
+  }
+  else {
+    w.write_all(&[(major << 5) | 24, n])
+  }
 }
 
-fn ser_u16(major: u8, n: u16) -> Vec<u8> {
+fn ser_u16<W: Write>(w: &mut Writer<W>, major: u8, n: u16) -> Result<()> {
   if n <= 255 {
-    ser_u8(major, u8::try_from(n).unwrap())
+    ser_u8(w, major, u8::try_from(n).unwrap())
   }
   else {
-    let mut buf = vec![(major << 5) | 25];
-    buf.extend(n.to_be_bytes());
-    buf
+    w.write_all(&[(major << 5) | 25])?;
+    w.write_all(&n.to_be_bytes())
   }
 }
 
-fn ser_u32(major: u8, n: u32) -> Vec<u8> {
+fn ser_u32<W: Write>(w: &mut Writer<W>, major: u8, n: u32) -> Result<()> {
   if n <= 65535 {
-    ser_u16(major, u16::try_from(n).unwrap())
+    ser_u16(w, major, u16::try_from(n).unwrap())
   }
   else {
-    let mut buf = vec![(major << 5) | 26];
-    buf.extend(n.to_be_bytes());
-    buf
+    w.write_all(&[(major << 5) | 26])?;
+    w.write_all(&n.to_be_bytes())
   }
 }
 
-fn ser_u64(major: u8, n: u64) -> Vec<u8> {
+fn ser_u64<W: Write>(w: &mut Writer<W>, major: u8, n: u64) -> Result<()> {
   if n <= 4294967295 {
-    ser_u32(major, u32::try_from(n).unwrap())
+    ser_u32(w, major, u32::try_from(n).unwrap())
+  }
+  else {
+    w.write_all(&[(major << 5) | 27])?;
+    w.write_all(&n.to_be_bytes())
+  }
+}
+
+// Non-negative integers use major type 0, negative integers `n` are stored as
+// `-1 - n` under major type 1
+fn ser_integer<W: Write>(w: &mut Writer<W>, i: i128) -> Result<()> {
+  if let Ok(n) = u64::try_from(i) {
+    ser_u64(w, 0, n)
+  }
+  else if let Ok(n) = u64::try_from(-1 - i) {
+    ser_u64(w, 1, n)
   }
   else {
-    let mut buf = vec![(major << 5) | 27];
-    buf.extend(n.to_be_bytes());
-    buf
+    Err(IntegerOutOfRange(i))
   }
 }
 
-fn ser_string(s: &String) -> Vec<u8> {
+// DAG-CBOR always encodes floats with 64 bits and forbids NaN and infinities
+fn ser_float<W: Write>(w: &mut Writer<W>, f: f64) -> Result<()> {
+  if !f.is_finite() {
+    return Err(NonFiniteFloat);
+  }
+  w.write_all(&[0xfb])?;
+  w.write_all(&f.to_be_bytes())
+}
+
+fn ser_string<W: Write>(w: &mut Writer<W>, s: &str) -> Result<()> {
   let str_bytes = s.as_bytes();
-  let mut result = ser_u64(3, str_bytes.len() as u64);
-  result.extend(str_bytes);
-  result
+  ser_u64(w, 3, str_bytes.len() as u64)?;
+  w.write_all(str_bytes)
 }
 
-fn ser_bytes(b: &Vec<u8>) -> Vec<u8> {
-  let mut result = ser_u64(2, b.len() as u64);
-  result.extend(b);
-  result
+fn ser_bytes<W: Write>(w: &mut Writer<W>, b: &[u8]) -> Result<()> {
+  ser_u64(w, 2, b.len() as u64)?;
+  w.write_all(b)
 }
 
-fn ser_link(l: &Cid) -> Vec<u8> {
-  let buf = l.to_bytes();
-  let mut result = ser_u64(6, 42);
-  result.extend(ser_u64(2, (buf.len() + 1) as u64));
-  result.push(0);
-  result.extend(buf);
-  result
+fn ser_link<W: Write>(w: &mut Writer<W>, l: &Cid) -> Result<()> {
+  let buf = l.to_bytes().map_err(InvalidLink)?;
+  ser_u64(w, 6, 42)?;
+  ser_u64(w, 2, (buf.len() + 1) as u64)?;
+  w.write_all(&[0])?;
+  w.write_all(&buf)
 }
 
-fn ser_array(a: &Vec<Ipld>) -> Vec<u8> {
-  let mut result = ser_u64(4, a.len() as u64);
+fn ser_array<W: Write>(w: &mut Writer<W>, a: &[Ipld]) -> Result<()> {
+  ser_u64(w, 4, a.len() as u64)?;
   for ipld in a {
-    result.extend(serialize(ipld));
+    ser_ipld(w, ipld)?;
   }
-  result
+  Ok(())
 }
 
-fn ser_object(m: &BTreeMap<String, Ipld>) -> Vec<u8> {
-  let mut result = ser_u64(5, m.len() as u64);
-  for (key, val) in m.iter() {
-    result.extend(ser_string(key));
-    result.extend(serialize(val));
+fn ser_object<W: Write>(
+  w: &mut Writer<W>,
+  m: &BTreeMap<String, Ipld>,
+) -> Result<()> {
+  ser_u64(w, 5, m.len() as u64)?;
+  for (key, val) in canonical_entries(m) {
+    ser_string(w, key)?;
+    ser_ipld(w, val)?;
+  }
+  Ok(())
+}
+
+/// Computes the exact number of bytes [`serialize`] produces for `ipld`,
+/// failing on the same values it does.
+pub fn encoded_len(ipld: &Ipld) -> Result<u64> {
+  match ipld {
+    Ipld::Null | Ipld::Bool(_) => Ok(1),
+    Ipld::Integer(i) => {
+      if let Ok(n) = u64::try_from(*i) {
+        Ok(head_len(n))
+      }
+      else if let Ok(n) = u64::try_from(-1 - i) {
+        Ok(head_len(n))
+      }
+      else {
+        Err(IntegerOutOfRange(*i))
+      }
+    }
+    Ipld::Float(f) if f.is_finite() => Ok(9),
+    Ipld::Float(_) => Err(NonFiniteFloat),
+    Ipld::String(s) => Ok(head_len(s.len() as u64) + s.len() as u64),
+    Ipld::Bytes(b) => Ok(head_len(b.len() as u64) + b.len() as u64),
+    Ipld::Array(a) => {
+      let mut len = head_len(a.len() as u64);
+      for ipld in a {
+        len += encoded_len(ipld)?;
+      }
+      Ok(len)
+    }
+    Ipld::Object(o) => {
+      let mut len = head_len(o.len() as u64);
+      for (key, val) in o {
+        len += head_len(key.len() as u64) + key.len() as u64;
+        len += encoded_len(val)?;
+      }
+      Ok(len)
+    }
+    Ipld::Link(c) => {
+      let cid_len = c.to_bytes().map_err(InvalidLink)?.len() as u64 + 1;
+      Ok(head_len(42) + head_len(cid_len) + cid_len)
+    }
+  }
+}
+
+// Length of the head encoding the argument `n`
+fn head_len(n: u64) -> u64 {
+  match n {
+    0..=0x17 => 1,
+    0x18..=0xff => 2,
+    0x100..=0xffff => 3,
+    0x10000..=0xffffffff => 5,
+    _ => 9,
+  }
+}
+
+/// Returns the entries of a map in DAG-CBOR canonical order: keys sorted by
+/// the length of their encoding first and bytewise second. Since every key is
+/// a string of the same major type, this is the UTF-8 length followed by the
+/// UTF-8 bytes.
+pub(crate) fn canonical_entries(
+  m: &BTreeMap<String, Ipld>,
+) -> Vec<(&String, &Ipld)> {
+  let mut entries: Vec<(&String, &Ipld)> = m.iter().collect();
+  entries.sort_by(|(a, _), (b, _)| {
+    a.len().cmp(&b.len()).then_with(|| a.as_bytes().cmp(b.as_bytes()))
+  });
+  entries
+}
+
+/// How strictly the decoder checks that its input is in DAG-CBOR canonical
+/// form.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+  /// Accepts any well-formed CBOR that maps onto the IPLD data model,
+  /// including non-minimal integers, unsorted or duplicate map keys (the last
+  /// value wins), indefinite lengths, `undefined`, 16 and 32-bit floats and
+  /// unknown tags (which are dropped). Useful for ingesting foreign CBOR.
+  #[default]
+  Lenient,
+  /// Rejects everything but the single canonical encoding of each value, so
+  /// that decoding and re-encoding always reproduces the input bytes.
+  Strict,
+}
+
+/// Bounds on the resources the decoder may use, so that untrusted input
+/// cannot exhaust memory or the stack. Lengths are checked against the limits
+/// before anything is allocated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeLimits {
+  /// Maximum number of arrays, maps and tags enclosing any value.
+  pub max_depth: usize,
+  /// Maximum number of bytes the decoded value may occupy, counting string
+  /// and bytes contents plus one `Ipld` slot per array element or map entry.
+  pub max_allocation: u64,
+  /// Maximum number of elements in a single array or entries in a map.
+  pub max_collection_len: u64,
+  /// Maximum length in bytes of a single string or byte string.
+  pub max_string_len: u64,
+}
+
+impl DecodeLimits {
+  /// No limits besides the available memory and stack.
+  pub fn unlimited() -> Self {
+    DecodeLimits {
+      max_depth: usize::MAX,
+      max_allocation: u64::MAX,
+      max_collection_len: u64::MAX,
+      max_string_len: u64::MAX,
+    }
+  }
+}
+
+/// The default depth keeps recursion well within the 2 MiB stack of a spawned
+/// thread even in debug builds.
+impl Default for DecodeLimits {
+  fn default() -> Self {
+    DecodeLimits {
+      max_depth: 128,
+      max_allocation: 1 << 30,
+      max_collection_len: 1 << 24,
+      max_string_len: 1 << 30,
+    }
   }
-  result
 }
 
-// TODO: Move this to error.rs
-#[derive(Error, Debug)]
-enum DeserializeError {
-  //#[error("Unexpected CBOR tag: `{0}`")]
-  // UnknownCborTag (tag: u8),
-  #[error("Unknown CBOR tag")]
-  UnknownCborTag,
-  #[error("Unexpected CBOR code")]
-  UnexpectedCborCode,
-  #[error("CID length too large")]
-  CidLenOutofRange,
-  #[error("Invalid CID prefix")]
-  CidPrefix,
-  #[error("Failed to read CID")]
-  CidRead,
+/// Options for [`deserialize_with`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecodeOptions {
+  pub mode: Mode,
+  pub limits: DecodeLimits,
 }
 
-use crate::dag_cbor::DeserializeError::*;
+const IPLD_SLOT: u64 = mem::size_of::<Ipld>() as u64;
 
+/// Wraps the input and counts the bytes consumed so far, so that errors can
+/// point at the offending offset. Also tracks the resources used against the
+/// decode limits.
+struct Reader<'a, R> {
+  inner: &'a mut R,
+  offset: u64,
+  strict: bool,
+  limits: DecodeLimits,
+  depth: usize,
+  allocated: u64,
+}
+
+impl<'a, R: Read> Reader<'a, R> {
+  fn new(inner: &'a mut R, opts: &DecodeOptions) -> Self {
+    Reader {
+      inner,
+      offset: 0,
+      strict: opts.mode == Mode::Strict,
+      limits: opts.limits,
+      depth: 0,
+      allocated: 0,
+    }
+  }
+
+  fn alloc(&mut self, bytes: u64, offset: u64) -> Result<()> {
+    self.allocated = self.allocated.saturating_add(bytes);
+    if self.allocated > self.limits.max_allocation {
+      return Err(AllocationLimit { offset });
+    }
+    Ok(())
+  }
+
+  fn alloc_string(&mut self, len: u64, offset: u64) -> Result<()> {
+    if len > self.limits.max_string_len {
+      return Err(StringLimit { len, offset });
+    }
+    self.alloc(len, offset)
+  }
+
+  // Accounts for a collection whose length of `len` is announced up front
+  fn alloc_collection(&mut self, len: u64, offset: u64) -> Result<()> {
+    if len > self.limits.max_collection_len {
+      return Err(CollectionLimit { len, offset });
+    }
+    self.alloc(len.saturating_mul(IPLD_SLOT), offset)
+  }
+
+  // Accounts for one more element of an indefinite-length collection, which
+  // then holds `len` elements
+  fn alloc_element(&mut self, len: u64, offset: u64) -> Result<()> {
+    if len > self.limits.max_collection_len {
+      return Err(CollectionLimit { len, offset });
+    }
+    self.alloc(IPLD_SLOT, offset)
+  }
+
+  fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+    let offset = self.offset;
+    self.inner.read_exact(buf).map_err(|e| io_error(e, offset))?;
+    self.offset += buf.len() as u64;
+    Ok(())
+  }
+
+  fn at_eof(&mut self) -> Result<bool> {
+    let mut buf = [0; 1];
+    let n = self.inner.read(&mut buf).map_err(|e| io_error(e, self.offset))?;
+    Ok(n == 0)
+  }
+}
+
+fn io_error(e: io::Error, offset: u64) -> DagCborError {
+  match e.kind() {
+    io::ErrorKind::UnexpectedEof => UnexpectedEof { offset },
+    kind => Io { offset, kind },
+  }
+}
+
+/// Decodes a single value in lenient mode with the default limits, leaving any
+/// bytes after it unread.
 pub fn deserialize<R: Read>(r: &mut R) -> Result<Ipld> {
+  deserialize_with(r, &DecodeOptions::default())
+}
+
+/// Decodes a single value in strict mode, rejecting any non-canonical input
+/// including trailing bytes.
+pub fn deserialize_strict<R: Read>(r: &mut R) -> Result<Ipld> {
+  deserialize_with(r, &DecodeOptions {
+    mode: Mode::Strict,
+    ..DecodeOptions::default()
+  })
+}
+
+pub fn deserialize_with<R: Read>(
+  r: &mut R,
+  opts: &DecodeOptions,
+) -> Result<Ipld> {
+  let mut r = Reader::new(r, opts);
+  let ipld = read_ipld(&mut r)?;
+  if r.strict && !r.at_eof()? {
+    return Err(TrailingBytes { offset: r.offset });
+  }
+  Ok(ipld)
+}
+
+fn read_ipld<R: Read>(r: &mut Reader<R>) -> Result<Ipld> {
   let major = read_u8(r)?;
+  read_item(r, major)
+}
+
+// Reads the initial byte of the next chunk or element of an indefinite-length
+// item, returning `None` at the break code
+fn read_until_break<R: Read>(r: &mut Reader<R>) -> Result<Option<u8>> {
+  match read_u8(r)? {
+    0xff => Ok(None),
+    major => Ok(Some(major)),
+  }
+}
+
+fn read_item<R: Read>(r: &mut Reader<R>, major: u8) -> Result<Ipld> {
+  let offset = r.offset - 1;
+  if r.depth > r.limits.max_depth {
+    return Err(DepthLimit { offset });
+  }
+  r.depth += 1;
+  let ipld = read_item_at(r, major, offset);
+  r.depth -= 1;
+  ipld
+}
+
+fn read_item_at<R: Read>(
+  r: &mut Reader<R>,
+  major: u8,
+  offset: u64,
+) -> Result<Ipld> {
   match major {
-    0x00..=0x17 => Ok(Ipld::Number(major as u64)),
-    0x18 => Ok(Ipld::Number(read_u8(r)? as u64)),
-    0x19 => Ok(Ipld::Number(read_u16(r)? as u64)),
-    0x1a => Ok(Ipld::Number(read_u32(r)? as u64)),
-    0x1b => Ok(Ipld::Number(read_u64(r)?)),
+    // Major type 0: Unsigned integer
+    0x00..=0x1b => Ok(Ipld::Integer(read_len(r, major)? as i128)),
+    // Major type 1: Negative integer
+    0x20..=0x3b => Ok(Ipld::Integer(-1 - read_len(r, major - 0x20)? as i128)),
     // Major type 2: Byte string
     0x40..=0x5b => {
       let len = read_len(r, major - 0x40)?;
       Ok(Ipld::Bytes(read_bytes(r, len)?))
     }
+    0x5f => {
+      indefinite(r, offset)?;
+      Ok(Ipld::Bytes(read_indefinite_bytes(r)?))
+    }
     // Major type 3: Text string
     0x60..=0x7b => {
       let len = read_len(r, major - 0x60)?;
       Ok(Ipld::String(read_string(r, len)?))
     }
+    0x7f => {
+      indefinite(r, offset)?;
+      Ok(Ipld::String(read_indefinite_string(r)?))
+    }
     // Major type 4: Array
     0x80..=0x9b => {
       let len = read_len(r, major - 0x80)?;
-      let mut arr = vec![];
-      for _ in 0..len {
-        if let Ok(ipld) = deserialize(r) {
-          arr.push(ipld);
-        }
-        else {
-          return Err(UnknownCborTag.into());
-        }
-      }
-      Ok(Ipld::Array(arr))
+      read_array(r, Some(len), offset)
+    }
+    0x9f => {
+      indefinite(r, offset)?;
+      read_array(r, None, offset)
     }
     // Major type 5: Map
     0xa0..=0xbb => {
       let len = read_len(r, major - 0xa0)?;
-      let mut map: BTreeMap<String, Ipld> = BTreeMap::new();
-      for _ in 0..len {
-        let major = read_u8(r)?;
-        let len = read_len(r, major - 0x60)?;
-        let key = read_string(r, len)?;
-        if let Ok(val) = deserialize(r) {
-          map.insert(key, val);
-        }
-        else {
-          return Err(UnknownCborTag.into());
-        }
-      }
-      Ok(Ipld::Object(map))
+      read_map(r, Some(len), offset)
     }
-    // Map of pairs?
-    // 0xbf => {},
-    // Major type 6: CID Tag
-    0xd8 => {
-      let tag = read_u8(r)?;
+    0xbf => {
+      indefinite(r, offset)?;
+      read_map(r, None, offset)
+    }
+    // Major type 6: Tags, of which only 42 (CID) is part of DAG-CBOR
+    0xc0..=0xdb => {
+      let tag = read_len(r, major - 0xc0)?;
       if tag == 42 {
         Ok(Ipld::Link(read_link(r)?))
       }
+      else if r.strict {
+        Err(UnknownCborTag { tag, offset })
+      }
       else {
-        Err(UnknownCborTag.into())
+        read_ipld(r)
       }
     }
-    // Major type 1: Bool
+    // Major type 7: Simple values and floats
     0xf4 => Ok(Ipld::Bool(false)),
     0xf5 => Ok(Ipld::Bool(true)),
-    // Major type 0: Null
-    0xf6..=0xf7 => Ok(Ipld::Null),
-    _ => Err(UnknownCborTag.into()),
+    0xf6 => Ok(Ipld::Null),
+    0xf7 if r.strict => Err(Undefined { offset }),
+    0xf7 => Ok(Ipld::Null),
+    0xf9 | 0xfa if r.strict => Err(NonCanonicalFloat { offset }),
+    0xf9 => Ok(Ipld::Float(f16_to_f64(read_u16(r)?))),
+    0xfa => Ok(Ipld::Float(f32::from_bits(read_u32(r)?) as f64)),
+    0xfb => {
+      let f = f64::from_bits(read_u64(r)?);
+      if r.strict && !f.is_finite() {
+        return Err(NonCanonicalFloat { offset });
+      }
+      Ok(Ipld::Float(f))
+    }
+    _ => Err(UnexpectedCborCode { code: major, offset }),
   }
 }
 
-fn read_len<R: Read>(r: &mut R, len: u8) -> Result<u64> {
+fn indefinite<R: Read>(r: &Reader<R>, offset: u64) -> Result<()> {
+  if r.strict {
+    Err(IndefiniteLength { offset })
+  }
+  else {
+    Ok(())
+  }
+}
+
+// The elements of arrays and maps are read in helpers of their own to keep the
+// stack frame of `read_item_at`, and with it the cost of each nesting level,
+// small.
+
+// Reads the elements of an array, up to the break code if `len` is `None`
+fn read_array<R: Read>(
+  r: &mut Reader<R>,
+  len: Option<u64>,
+  offset: u64,
+) -> Result<Ipld> {
+  let mut arr = vec![];
+  match len {
+    Some(len) => {
+      r.alloc_collection(len, offset)?;
+      for _ in 0..len {
+        arr.push(read_ipld(r)?);
+      }
+    }
+    None => {
+      while let Some(major) = read_until_break(r)? {
+        r.alloc_element(arr.len() as u64 + 1, offset)?;
+        arr.push(read_item(r, major)?);
+      }
+    }
+  }
+  Ok(Ipld::Array(arr))
+}
+
+// Reads the entries of a map, up to the break code if `len` is `None`
+fn read_map<R: Read>(
+  r: &mut Reader<R>,
+  len: Option<u64>,
+  offset: u64,
+) -> Result<Ipld> {
+  let mut map: BTreeMap<String, Ipld> = BTreeMap::new();
+  let mut prev: Option<String> = None;
   match len {
-    0x00..=0x17 => Ok(len as u64),
-    0x18 => read_u8(r).map(|x| x as u64),
-    0x19 => read_u16(r).map(|x| x as u64),
-    0x1a => read_u32(r).map(|x| x as u64),
-    0x1b => read_u64(r),
-    _ => Err(UnexpectedCborCode.into()),
+    Some(len) => {
+      r.alloc_collection(len, offset)?;
+      for _ in 0..len {
+        let major = read_u8(r)?;
+        let (key, val) = read_entry(r, major, &mut prev)?;
+        map.insert(key, val);
+      }
+    }
+    None => {
+      while let Some(major) = read_until_break(r)? {
+        r.alloc_element(map.len() as u64 + 1, offset)?;
+        let (key, val) = read_entry(r, major, &mut prev)?;
+        map.insert(key, val);
+      }
+    }
+  }
+  Ok(Ipld::Object(map))
+}
+
+// Reads a map key, which must be a string, and its value. In strict mode the
+// keys must also be unique and in canonical order.
+fn read_entry<R: Read>(
+  r: &mut Reader<R>,
+  major: u8,
+  prev: &mut Option<String>,
+) -> Result<(String, Ipld)> {
+  let offset = r.offset - 1;
+  let key = match major {
+    0x60..=0x7b => {
+      let len = read_len(r, major - 0x60)?;
+      read_string(r, len)?
+    }
+    0x7f if !r.strict => read_indefinite_string(r)?,
+    0x7f => return Err(IndefiniteLength { offset }),
+    _ => return Err(NonStringKey { offset }),
+  };
+  if r.strict {
+    if let Some(prev) = prev {
+      let order = prev
+        .len()
+        .cmp(&key.len())
+        .then_with(|| prev.as_bytes().cmp(key.as_bytes()));
+      match order {
+        Ordering::Equal => return Err(DuplicateKey { key, offset }),
+        Ordering::Greater => return Err(UnsortedKeys { offset }),
+        Ordering::Less => (),
+      }
+    }
+    *prev = Some(key.clone());
+  }
+  let val = read_ipld(r)?;
+  Ok((key, val))
+}
+
+fn read_indefinite_bytes<R: Read>(r: &mut Reader<R>) -> Result<Vec<u8>> {
+  let offset = r.offset - 1;
+  let mut bytes = vec![];
+  while let Some(major) = read_until_break(r)? {
+    if !(0x40..=0x5b).contains(&major) {
+      return Err(UnexpectedCborCode { code: major, offset: r.offset - 1 });
+    }
+    let len = read_len(r, major - 0x40)?;
+    let total = (bytes.len() as u64).saturating_add(len);
+    if total > r.limits.max_string_len {
+      return Err(StringLimit { len: total, offset });
+    }
+    bytes.extend(read_bytes(r, len)?);
+  }
+  Ok(bytes)
+}
+
+fn read_indefinite_string<R: Read>(r: &mut Reader<R>) -> Result<String> {
+  let offset = r.offset - 1;
+  let mut string = String::new();
+  while let Some(major) = read_until_break(r)? {
+    if !(0x60..=0x7b).contains(&major) {
+      return Err(UnexpectedCborCode { code: major, offset: r.offset - 1 });
+    }
+    let len = read_len(r, major - 0x60)?;
+    let total = (string.len() as u64).saturating_add(len);
+    if total > r.limits.max_string_len {
+      return Err(StringLimit { len: total, offset });
+    }
+    string.push_str(&read_string(r, len)?);
   }
+  Ok(string)
 }
 
-fn read_u8<R: Read>(r: &mut R) -> Result<u8> {
+// Reads the argument of a head whose initial byte has just been consumed. In
+// strict mode the argument must use the shortest possible encoding.
+fn read_len<R: Read>(r: &mut Reader<R>, len: u8) -> Result<u64> {
+  let offset = r.offset - 1;
+  let (n, min) = match len {
+    0x00..=0x17 => return Ok(len as u64),
+    0x18 => (read_u8(r)? as u64, 0x18),
+    0x19 => (read_u16(r)? as u64, 0x100),
+    0x1a => (read_u32(r)? as u64, 0x10000),
+    0x1b => (read_u64(r)?, 0x100000000),
+    _ => return Err(UnexpectedCborCode { code: len, offset }),
+  };
+  if r.strict && n < min {
+    return Err(NonMinimalInteger { offset });
+  }
+  Ok(n)
+}
+
+fn read_u8<R: Read>(r: &mut Reader<R>) -> Result<u8> {
   let mut buf = [0; 1];
   r.read_exact(&mut buf)?;
   Ok(buf[0])
 }
 
-fn read_u16<R: Read>(r: &mut R) -> Result<u16> {
+fn read_u16<R: Read>(r: &mut Reader<R>) -> Result<u16> {
   let mut buf = [0; 2];
   r.read_exact(&mut buf)?;
   Ok(u16::from_be_bytes(buf))
 }
 
-fn read_u32<R: Read>(r: &mut R) -> Result<u32> {
+fn read_u32<R: Read>(r: &mut Reader<R>) -> Result<u32> {
   let mut buf = [0; 4];
   r.read_exact(&mut buf)?;
   Ok(u32::from_be_bytes(buf))
 }
 
-fn read_u64<R: Read>(r: &mut R) -> Result<u64> {
+fn read_u64<R: Read>(r: &mut Reader<R>) -> Result<u64> {
   let mut buf = [0; 8];
   r.read_exact(&mut buf)?;
   Ok(u64::from_be_bytes(buf))
 }
 
-fn read_bytes<R: Read>(r: &mut R, len: u64) -> Result<Vec<u8>> {
-  let mut buf = vec![0; len as usize];
-  r.read_exact(&mut buf)?;
+// Widens an IEEE 754 half-precision float
+fn f16_to_f64(half: u16) -> f64 {
+  let sign = if half & 0x8000 == 0 { 1.0 } else { -1.0 };
+  let exp = ((half >> 10) & 0x1f) as i32;
+  let mant = (half & 0x3ff) as f64;
+  match exp {
+    0 => sign * mant * 2f64.powi(-24),
+    0x1f if mant == 0.0 => sign * f64::INFINITY,
+    0x1f => f64::NAN,
+    _ => sign * (1.0 + mant / 1024.0) * 2f64.powi(exp - 15),
+  }
+}
+
+fn read_bytes<R: Read>(r: &mut Reader<R>, len: u64) -> Result<Vec<u8>> {
+  let offset = r.offset;
+  r.alloc_string(len, offset)?;
+  // Read through `take` so that a bogus length within the limits still cannot
+  // trigger a large up-front allocation on truncated input.
+  let mut buf = vec![];
+  let found = (&mut r.inner)
+    .take(len)
+    .read_to_end(&mut buf)
+    .map_err(|e| io_error(e, offset))?;
+  r.offset += found as u64;
+  if (found as u64) < len {
+    return Err(UnexpectedEof { offset: r.offset });
+  }
   Ok(buf)
 }
 
-fn read_string<R: Read>(r: &mut R, len: u64) -> Result<String> {
+fn read_string<R: Read>(r: &mut Reader<R>, len: u64) -> Result<String> {
+  let offset = r.offset;
   let bytes = read_bytes(r, len)?;
-  Ok(String::from_utf8(bytes)?)
+  String::from_utf8(bytes).map_err(|_| InvalidUtf8 { offset })
 }
 
-fn read_link<R: Read>(r: &mut R) -> Result<Cid> {
-  let ty = read_u8(r)?;
-  if ty != 0x58 {
-    return Err(UnknownCborTag.into());
+fn read_link<R: Read>(r: &mut Reader<R>) -> Result<Cid> {
+  let offset = r.offset;
+  let major = read_u8(r)?;
+  if !(0x40..=0x5b).contains(&major) {
+    return Err(UnexpectedCborCode { code: major, offset });
   }
-  let len = read_u8(r)? as u64;
+  let len = read_len(r, major - 0x40)?;
   if len == 0 {
-    return Err(CidLenOutofRange.into());
+    return Err(CidLenOutofRange { offset });
   }
+  let offset = r.offset;
   let bytes = read_bytes(r, len)?;
   if bytes[0] != 0 {
-    return Err(CidPrefix.into());
+    return Err(CidPrefix { offset });
   }
-  Cid::from_bytes(&mut &bytes[..])
+  let cid_error = |source| DagCborError::Cid { offset: offset + 1, source };
+  // The whole byte string must be the CID, or the encoding would not be unique
+  let mut rest = &bytes[1..];
+  let cid = Cid::from_bytes(&mut rest).map_err(cid_error)?;
+  if !rest.is_empty() {
+    return Err(cid_error(CidError::TrailingBytes));
+  }
+  Ok(cid)
 }
 
 #[cfg(test)]
@@ -265,7 +759,18 @@ mod tests {
     cid::Cid,
     dag_cbor::{
       deserialize,
+      deserialize_strict,
+      deserialize_with,
+      encoded_len,
       serialize,
+      serialize_into,
+      DecodeLimits,
+      DecodeOptions,
+    },
+    error::{
+      CidError,
+      DagCborError,
+      MultihashError,
     },
     ipld::Ipld,
     multihash::Multihash,
@@ -275,15 +780,16 @@ mod tests {
   fn serde_roundtrip() {
     let ipld_null = Ipld::Null;
     let ipld_bool = Ipld::Bool(true);
-    let ipld_number = Ipld::Number(0x17);
-    let ipld_number_big = Ipld::Number(0x10000);
+    let ipld_number = Ipld::Integer(0x17);
+    let ipld_number_big = Ipld::Integer(0x10000);
     let ipld_string = Ipld::String("Hello".into());
     let ipld_bytes = Ipld::Bytes(vec![0, 8, 4, 0]);
     let ipld_array = Ipld::Array(vec![Ipld::String("Hello".into())]);
     let ipld_object =
       Ipld::to_object(vec![("Hello".into(), Ipld::String("World".into()))]);
-    let cid = Cid::new(1, 0x71, Multihash::sha3_256(&serialize(&ipld_null)));
-    let _ipld_link = Ipld::Link(cid);
+    let cid =
+      Cid::new(1, 0x71, Multihash::sha3_256(&serialize(&ipld_null).unwrap()));
+    let ipld_link = Ipld::Link(cid);
     // assert_eq!(serialize(&ipld_null), vec![0xf6]);
     // assert_eq!(serialize(&ipld_bool), vec![0xf5]);
     // assert_eq!(serialize(&ipld_number), vec![23]);
@@ -301,38 +807,324 @@ mod tests {
     //]);
     assert_eq!(
       ipld_null,
-      deserialize(&mut &serialize(&ipld_null)[..]).unwrap()
+      deserialize(&mut &serialize(&ipld_null).unwrap()[..]).unwrap()
     );
     assert_eq!(
       ipld_bool,
-      deserialize(&mut &serialize(&ipld_bool)[..]).unwrap()
+      deserialize(&mut &serialize(&ipld_bool).unwrap()[..]).unwrap()
     );
     assert_eq!(
       ipld_number,
-      deserialize(&mut &serialize(&ipld_number)[..]).unwrap()
+      deserialize(&mut &serialize(&ipld_number).unwrap()[..]).unwrap()
     );
     assert_eq!(
       ipld_number_big,
-      deserialize(&mut &serialize(&ipld_number_big)[..]).unwrap()
+      deserialize(&mut &serialize(&ipld_number_big).unwrap()[..]).unwrap()
     );
     assert_eq!(
       ipld_string,
-      deserialize(&mut &serialize(&ipld_string)[..]).unwrap()
+      deserialize(&mut &serialize(&ipld_string).unwrap()[..]).unwrap()
     );
     assert_eq!(
       ipld_bytes,
-      deserialize(&mut &serialize(&ipld_bytes)[..]).unwrap()
+      deserialize(&mut &serialize(&ipld_bytes).unwrap()[..]).unwrap()
     );
     assert_eq!(
       ipld_array,
-      deserialize(&mut &serialize(&ipld_array)[..]).unwrap()
+      deserialize(&mut &serialize(&ipld_array).unwrap()[..]).unwrap()
     );
     assert_eq!(
       ipld_object,
-      deserialize(&mut &serialize(&ipld_object)[..]).unwrap()
+      deserialize(&mut &serialize(&ipld_object).unwrap()[..]).unwrap()
+    );
+    assert_eq!(
+      ipld_link,
+      deserialize(&mut &serialize(&ipld_link).unwrap()[..]).unwrap()
+    );
+  }
+
+  #[test]
+  fn deserialize_malformed() {
+    let err = |bytes: &[u8]| deserialize(&mut &bytes[..]).unwrap_err();
+    assert_eq!(err(&[]), DagCborError::UnexpectedEof { offset: 0 });
+    assert_eq!(err(&[0x19, 0x01]), DagCborError::UnexpectedEof { offset: 1 });
+    assert_eq!(err(&[0x65, b'H', b'e']), DagCborError::UnexpectedEof {
+      offset: 3
+    });
+    assert_eq!(
+      err(&[0x5a, 0x00, 0x10, 0x00, 0x00, 0x00]),
+      DagCborError::UnexpectedEof { offset: 6 }
+    );
+    assert_eq!(err(&[0x62, 0xc3, 0x28]), DagCborError::InvalidUtf8 {
+      offset: 1
+    });
+    assert_eq!(err(&[0x82, 0x01, 0xff]), DagCborError::UnexpectedCborCode {
+      code: 0xff,
+      offset: 2
+    });
+    assert_eq!(err(&[0xa1, 0x01, 0x01]), DagCborError::NonStringKey {
+      offset: 1
+    });
+    assert_eq!(
+      err(&[0xd8, 0x2a, 0x58, 0x00]),
+      DagCborError::CidLenOutofRange { offset: 2 }
+    );
+    assert_eq!(err(&[0xd8, 0x2a, 0x58, 0x01, 0x01]), DagCborError::CidPrefix {
+      offset: 4
+    });
+
+    let cid = Cid::new(1, 0x71, Multihash::sha3_256(&[1]));
+    let mut link = serialize(&Ipld::Link(cid)).unwrap();
+    link[3] -= 1;
+    link.pop();
+    assert_eq!(err(&link), DagCborError::Cid {
+      offset: 5,
+      source: CidError::Multihash(MultihashError::Truncated {
+        expected: 32,
+        found: 31
+      })
+    });
+    // Bytes after the CID would give the link a second encoding
+    link[3] += 2;
+    link.extend([0x01, 0xaa]);
+    let trailing =
+      DagCborError::Cid { offset: 5, source: CidError::TrailingBytes };
+    assert_eq!(err(&link), trailing);
+    assert_eq!(deserialize_strict(&mut &link[..]).unwrap_err(), trailing);
+  }
+
+  #[test]
+  fn integers_and_floats() {
+    let cases: Vec<(Ipld, Vec<u8>)> = vec![
+      (Ipld::Integer(-1), vec![0x20]),
+      (Ipld::Integer(-24), vec![0x37]),
+      (Ipld::Integer(-25), vec![0x38, 0x18]),
+      (Ipld::Integer(-500), vec![0x39, 0x01, 0xf3]),
+      (Ipld::Integer(u64::MAX.into()), vec![
+        0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+      ]),
+      (Ipld::Integer(i64::MIN.into()), vec![
+        0x3b, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+      ]),
+      (Ipld::Integer(-1 - u64::MAX as i128), vec![
+        0x3b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+      ]),
+      (Ipld::Float(1.5), vec![0xfb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]),
+      (Ipld::Float(-4.1), vec![
+        0xfb, 0xc0, 0x10, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+      ]),
+    ];
+    for (ipld, bytes) in cases {
+      assert_eq!(serialize(&ipld).unwrap(), bytes);
+      assert_eq!(deserialize(&mut &bytes[..]).unwrap(), ipld);
+    }
+    assert_eq!(
+      deserialize(&mut &[0xf9, 0x3c, 0x00][..]).unwrap(),
+      Ipld::Float(1.0)
+    );
+    assert_eq!(
+      deserialize(&mut &[0xfa, 0x3f, 0xc0, 0x00, 0x00][..]).unwrap(),
+      Ipld::Float(1.5)
+    );
+    assert_eq!(
+      serialize(&Ipld::Integer(1 << 64)),
+      Err(DagCborError::IntegerOutOfRange(1 << 64))
+    );
+    assert_eq!(
+      serialize(&Ipld::Integer(-1 - (1 << 64))),
+      Err(DagCborError::IntegerOutOfRange(-1 - (1 << 64)))
+    );
+    assert_eq!(
+      serialize(&Ipld::Float(f64::NAN)),
+      Err(DagCborError::NonFiniteFloat)
+    );
+  }
+
+  #[test]
+  fn strict_mode() {
+    let strict = |bytes: &[u8]| deserialize_strict(&mut &bytes[..]);
+    let lenient = |bytes: &[u8]| deserialize(&mut &bytes[..]).unwrap();
+    let object = |kvs: Vec<(&str, i128)>| {
+      Ipld::to_object(
+        kvs.into_iter().map(|(k, v)| (k.into(), Ipld::Integer(v))).collect(),
+      )
+    };
+    let cases: Vec<(&[u8], DagCborError, Ipld)> = vec![
+      (
+        &[0x18, 0x05],
+        DagCborError::NonMinimalInteger { offset: 0 },
+        Ipld::Integer(5),
+      ),
+      (
+        &[0x39, 0x00, 0x05],
+        DagCborError::NonMinimalInteger { offset: 0 },
+        Ipld::Integer(-6),
+      ),
+      (
+        &[0x81, 0x78, 0x01, 0x61],
+        DagCborError::NonMinimalInteger { offset: 1 },
+        Ipld::Array(vec![Ipld::String("a".into())]),
+      ),
+      (
+        &[0xa2, 0x61, 0x62, 0x01, 0x61, 0x61, 0x02],
+        DagCborError::UnsortedKeys { offset: 4 },
+        object(vec![("a", 2), ("b", 1)]),
+      ),
+      (
+        &[0xa2, 0x62, 0x61, 0x61, 0x01, 0x61, 0x62, 0x02],
+        DagCborError::UnsortedKeys { offset: 5 },
+        object(vec![("aa", 1), ("b", 2)]),
+      ),
+      (
+        &[0xa2, 0x61, 0x61, 0x01, 0x61, 0x61, 0x02],
+        DagCborError::DuplicateKey { key: "a".into(), offset: 4 },
+        object(vec![("a", 2)]),
+      ),
+      (&[0xf7], DagCborError::Undefined { offset: 0 }, Ipld::Null),
+      (
+        &[0x9f, 0x01, 0xff],
+        DagCborError::IndefiniteLength { offset: 0 },
+        Ipld::Array(vec![Ipld::Integer(1)]),
+      ),
+      (
+        &[0x7f, 0x61, 0x61, 0x61, 0x62, 0xff],
+        DagCborError::IndefiniteLength { offset: 0 },
+        Ipld::String("ab".into()),
+      ),
+      (
+        &[0xbf, 0x61, 0x61, 0x01, 0xff],
+        DagCborError::IndefiniteLength { offset: 0 },
+        object(vec![("a", 1)]),
+      ),
+      (
+        &[0xc1, 0x01],
+        DagCborError::UnknownCborTag { tag: 1, offset: 0 },
+        Ipld::Integer(1),
+      ),
+      (
+        &[0xf9, 0x3c, 0x00],
+        DagCborError::NonCanonicalFloat { offset: 0 },
+        Ipld::Float(1.0),
+      ),
+      (
+        &[0xfb, 0x7f, 0xf0, 0, 0, 0, 0, 0, 0],
+        DagCborError::NonCanonicalFloat { offset: 0 },
+        Ipld::Float(f64::INFINITY),
+      ),
+      (&[0xf6, 0xf6], DagCborError::TrailingBytes { offset: 1 }, Ipld::Null),
+    ];
+    for (bytes, error, ipld) in cases {
+      assert_eq!(strict(bytes), Err(error));
+      assert_eq!(lenient(bytes), ipld);
+    }
+    let canonical = Ipld::Array(vec![
+      object(vec![("b", 1), ("aa", 2)]),
+      Ipld::Integer(-1000),
+      Ipld::Float(0.5),
+    ]);
+    assert_eq!(strict(&serialize(&canonical).unwrap()), Ok(canonical));
+  }
+
+  #[test]
+  fn decode_limits() {
+    let err = |bytes: &[u8]| deserialize(&mut &bytes[..]).unwrap_err();
+    let limited = |bytes: &[u8], limits: DecodeLimits| {
+      let opts = DecodeOptions { limits, ..DecodeOptions::default() };
+      deserialize_with(&mut &bytes[..], &opts)
+    };
+
+    // Nine bytes asking for an 18 exabyte byte string or array
+    assert_eq!(err(&[0x5b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]), {
+      DagCborError::StringLimit { len: u64::MAX, offset: 9 }
+    });
+    assert_eq!(err(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]), {
+      DagCborError::CollectionLimit { len: u64::MAX, offset: 0 }
+    });
+
+    // Nesting far beyond what the stack could handle
+    let mut deep = vec![0x81; 1_000_000];
+    deep.push(0x00);
+    assert_eq!(err(&deep), DagCborError::DepthLimit { offset: 129 });
+    let mut tags = vec![0xc1; 1_000_000];
+    tags.push(0x00);
+    assert_eq!(err(&tags), DagCborError::DepthLimit { offset: 129 });
+    let mut indefinite = vec![0x9f; 1_000_000];
+    indefinite.push(0x00);
+    assert_eq!(err(&indefinite), DagCborError::DepthLimit { offset: 129 });
+
+    // Custom limits
+    let small = DecodeLimits {
+      max_depth: 2,
+      max_allocation: 1000,
+      max_collection_len: 2,
+      max_string_len: 4,
+    };
+    assert_eq!(
+      limited(&[0x81, 0x81, 0x00], small),
+      Ok(Ipld::Array(vec![Ipld::Array(vec![Ipld::Integer(0)])]))
+    );
+    assert_eq!(
+      limited(&[0x81, 0x81, 0x81, 0x00], small),
+      Err(DagCborError::DepthLimit { offset: 3 })
+    );
+    assert_eq!(
+      limited(&[0x83, 0x01, 0x02, 0x03], small),
+      Err(DagCborError::CollectionLimit { len: 3, offset: 0 })
+    );
+    assert_eq!(
+      limited(&[0x9f, 0x01, 0x02, 0x03, 0xff], small),
+      Err(DagCborError::CollectionLimit { len: 3, offset: 0 })
+    );
+    assert_eq!(
+      limited(&[0x65, b'H', b'e', b'l', b'l', b'o'], small),
+      Err(DagCborError::StringLimit { len: 5, offset: 1 })
+    );
+    assert_eq!(
+      limited(&[0x7f, 0x63, b'H', b'e', b'l', 0x62, b'l', b'o', 0xff], small),
+      Err(DagCborError::StringLimit { len: 5, offset: 0 })
+    );
+    let strings = [0x82, 0x44, 1, 2, 3, 4, 0x44, 1, 2, 3, 4];
+    let tight = DecodeLimits {
+      max_allocation: 2 * std::mem::size_of::<Ipld>() as u64 + 7,
+      ..small
+    };
+    assert_eq!(
+      limited(&strings, tight),
+      Err(DagCborError::AllocationLimit { offset: 7 })
+    );
+    assert!(limited(&strings, DecodeLimits::unlimited()).is_ok());
+  }
+
+  #[test]
+  fn streaming_encoder() {
+    let ipld = Ipld::Array(vec![
+      Ipld::Integer(-1000),
+      Ipld::String("x".repeat(300)),
+      Ipld::Bytes(vec![7; 70000]),
+      Ipld::to_object(vec![
+        ("aa".into(), Ipld::Null),
+        ("b".into(), Ipld::Float(0.5)),
+      ]),
+    ]);
+    let bytes = serialize(&ipld).unwrap();
+    assert_eq!(encoded_len(&ipld).unwrap(), bytes.len() as u64);
+    let mut out = Vec::new();
+    serialize_into(&ipld, &mut out).unwrap();
+    assert_eq!(out, bytes);
+
+    // A writer that runs out of space reports where it stopped
+    let mut buf = [0u8; 10];
+    assert_eq!(
+      serialize_into(&ipld, &mut &mut buf[..]),
+      Err(DagCborError::Io { offset: 7, kind: std::io::ErrorKind::WriteZero })
+    );
+    assert_eq!(
+      encoded_len(&Ipld::Float(f64::NAN)),
+      Err(DagCborError::NonFiniteFloat)
+    );
+    assert_eq!(
+      encoded_len(&Ipld::Integer(i128::MAX)),
+      Err(DagCborError::IntegerOutOfRange(i128::MAX))
     );
-    // TODO: Fix Cid::from_bytes bug
-    // assert_eq!(ipld_link, deserialize(&mut
-    // &serialize(&ipld_link)[..]).unwrap());
   }
 }
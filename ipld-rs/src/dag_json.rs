@@ -0,0 +1,617 @@
+//! DAG-JSON (multicodec `0x0129`) encoding of [`Ipld`].
+//!
+//! Links are written as `{"/": "<cid>"}` with the CID string and bytes as
+//! `{"/": {"bytes": "<base64>"}}` with unpadded standard base64. The encoder
+//! produces canonical output: map keys sorted bytewise and no whitespace.
+
+use std::{
+  collections::BTreeMap,
+  io::{
+    Read,
+    Write,
+  },
+};
+
+use crate::{
+  error::DagJsonError::{
+    self,
+    *,
+  },
+  ipld::Ipld,
+  multibase::Base,
+};
+
+type Result<T> = std::result::Result<T, DagJsonError>;
+
+// Same bound as the default DAG-CBOR decoding limits
+const MAX_DEPTH: usize = 128;
+
+/// Encodes `ipld` as canonical DAG-JSON.
+pub fn serialize(ipld: &Ipld) -> Result<Vec<u8>> {
+  let mut buf = Vec::new();
+  serialize_into(ipld, &mut buf)?;
+  Ok(buf)
+}
+
+/// Encodes `ipld` as canonical DAG-JSON straight into `w`.
+pub fn serialize_into<W: Write>(ipld: &Ipld, w: &mut W) -> Result<()> {
+  ser_ipld(&mut Writer { inner: w, offset: 0 }, ipld)
+}
+
+struct Writer<'a, W> {
+  inner: &'a mut W,
+  offset: u64,
+}
+
+impl<'a, W: Write> Writer<'a, W> {
+  fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+    let offset = self.offset;
+    self.inner.write_all(buf).map_err(|e| Io { offset, kind: e.kind() })?;
+    self.offset += buf.len() as u64;
+    Ok(())
+  }
+}
+
+fn ser_ipld<W: Write>(w: &mut Writer<W>, ipld: &Ipld) -> Result<()> {
+  match ipld {
+    Ipld::Null => w.write_all(b"null"),
+    Ipld::Bool(true) => w.write_all(b"true"),
+    Ipld::Bool(false) => w.write_all(b"false"),
+    Ipld::Integer(i) if !in_range(*i) => Err(IntegerOutOfRange(*i)),
+    Ipld::Integer(i) => w.write_all(i.to_string().as_bytes()),
+    Ipld::Float(f) => ser_float(w, *f),
+    Ipld::String(s) => ser_string(w, s),
+    Ipld::Bytes(b) => {
+      w.write_all(br#"{"/":{"bytes":""#)?;
+      w.write_all(&Base::Base64.encode(b).as_bytes()[1..])?;
+      w.write_all(br#""}}"#)
+    }
+    Ipld::Array(a) => ser_array(w, a),
+    Ipld::Object(o) => ser_object(w, o),
+    Ipld::Link(c) => {
+      w.write_all(br#"{"/":""#)?;
+      w.write_all(c.try_to_string().map_err(InvalidLink)?.as_bytes())?;
+      w.write_all(br#""}"#)
+    }
+  }
+}
+
+// Same integer range as DAG-CBOR, which stores magnitudes in a u64
+fn in_range(i: i128) -> bool { (-(1 << 64)..1 << 64).contains(&i) }
+
+// The shortest representation that reads back as the same float, always with
+// a fraction or exponent so that it is not mistaken for an integer
+fn ser_float<W: Write>(w: &mut Writer<W>, f: f64) -> Result<()> {
+  if !f.is_finite() {
+    return Err(NonFiniteFloat);
+  }
+  w.write_all(format!("{:?}", f).as_bytes())
+}
+
+// Only `"`, `\` and control characters are escaped, everything else is
+// written as UTF-8
+fn ser_string<W: Write>(w: &mut Writer<W>, s: &str) -> Result<()> {
+  w.write_all(b"\"")?;
+  let mut start = 0;
+  for (i, c) in s.char_indices() {
+    let escape = match c {
+      '"' => "\\\"".to_string(),
+      '\\' => "\\\\".to_string(),
+      '\u{8}' => "\\b".to_string(),
+      '\u{c}' => "\\f".to_string(),
+      '\n' => "\\n".to_string(),
+      '\r' => "\\r".to_string(),
+      '\t' => "\\t".to_string(),
+      c if c < ' ' => format!("\\u{:04x}", c as u32),
+      _ => continue,
+    };
+    w.write_all(&s.as_bytes()[start..i])?;
+    w.write_all(escape.as_bytes())?;
+    start = i + 1;
+  }
+  w.write_all(&s.as_bytes()[start..])?;
+  w.write_all(b"\"")
+}
+
+fn ser_array<W: Write>(w: &mut Writer<W>, a: &[Ipld]) -> Result<()> {
+  w.write_all(b"[")?;
+  for (i, ipld) in a.iter().enumerate() {
+    if i > 0 {
+      w.write_all(b",")?;
+    }
+    ser_ipld(w, ipld)?;
+  }
+  w.write_all(b"]")
+}
+
+fn ser_object<W: Write>(
+  w: &mut Writer<W>,
+  m: &BTreeMap<String, Ipld>,
+) -> Result<()> {
+  if is_reserved(m) {
+    return Err(ReservedKey);
+  }
+  w.write_all(b"{")?;
+  // `BTreeMap` already iterates in bytewise key order
+  for (i, (key, val)) in m.iter().enumerate() {
+    if i > 0 {
+      w.write_all(b",")?;
+    }
+    ser_string(w, key)?;
+    w.write_all(b":")?;
+    ser_ipld(w, val)?;
+  }
+  w.write_all(b"}")
+}
+
+// Maps shaped like the link or bytes forms cannot be encoded, since they
+// would decode as a link or bytes instead of a map
+fn is_reserved(m: &BTreeMap<String, Ipld>) -> bool {
+  match single_entry(m, "/") {
+    Some(Ipld::String(_)) => true,
+    Some(Ipld::Object(inner)) => {
+      matches!(single_entry(inner, "bytes"), Some(Ipld::String(_)))
+    }
+    _ => false,
+  }
+}
+
+fn single_entry<'a>(
+  m: &'a BTreeMap<String, Ipld>,
+  key: &str,
+) -> Option<&'a Ipld> {
+  if m.len() == 1 {
+    m.get(key)
+  }
+  else {
+    None
+  }
+}
+
+/// Decodes a single DAG-JSON value that makes up all of `r`. Whitespace
+/// between tokens is accepted and map keys may appear in any order, but
+/// duplicate keys are rejected.
+pub fn deserialize<R: Read>(r: &mut R) -> Result<Ipld> {
+  let mut input = Vec::new();
+  r.read_to_end(&mut input)
+    .map_err(|e| Io { offset: input.len() as u64, kind: e.kind() })?;
+  let mut parser = Parser { input: &input, offset: 0, depth: 0 };
+  let ipld = parser.read_ipld()?;
+  parser.skip_whitespace();
+  match parser.peek() {
+    None => Ok(ipld),
+    Some(_) => Err(TrailingBytes { offset: parser.offset as u64 }),
+  }
+}
+
+struct Parser<'a> {
+  input: &'a [u8],
+  offset: usize,
+  depth: usize,
+}
+
+impl<'a> Parser<'a> {
+  fn peek(&self) -> Option<u8> { self.input.get(self.offset).copied() }
+
+  fn next(&mut self) -> Result<u8> {
+    let byte =
+      self.peek().ok_or(UnexpectedEof { offset: self.offset as u64 })?;
+    self.offset += 1;
+    Ok(byte)
+  }
+
+  fn unexpected(&self, byte: u8) -> DagJsonError {
+    UnexpectedByte { byte, offset: self.offset as u64 - 1 }
+  }
+
+  fn expect(&mut self, expected: u8) -> Result<()> {
+    match self.next()? {
+      byte if byte == expected => Ok(()),
+      byte => Err(self.unexpected(byte)),
+    }
+  }
+
+  fn skip_whitespace(&mut self) {
+    while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+      self.offset += 1;
+    }
+  }
+
+  fn read_ipld(&mut self) -> Result<Ipld> {
+    self.skip_whitespace();
+    match self.peek() {
+      Some(b'n') => self.read_literal("null", Ipld::Null),
+      Some(b't') => self.read_literal("true", Ipld::Bool(true)),
+      Some(b'f') => self.read_literal("false", Ipld::Bool(false)),
+      Some(b'"') => Ok(Ipld::String(self.read_string()?)),
+      Some(b'-' | b'0'..=b'9') => self.read_number(),
+      Some(b'[') => self.nested(Self::read_array),
+      Some(b'{') => self.nested(Self::read_map),
+      Some(byte) => {
+        self.offset += 1;
+        Err(self.unexpected(byte))
+      }
+      None => Err(UnexpectedEof { offset: self.offset as u64 }),
+    }
+  }
+
+  fn nested(&mut self, read: fn(&mut Self) -> Result<Ipld>) -> Result<Ipld> {
+    if self.depth >= MAX_DEPTH {
+      return Err(DepthLimit { offset: self.offset as u64 });
+    }
+    self.depth += 1;
+    let ipld = read(self)?;
+    self.depth -= 1;
+    Ok(ipld)
+  }
+
+  fn read_literal(&mut self, literal: &str, ipld: Ipld) -> Result<Ipld> {
+    for expected in literal.bytes() {
+      self.expect(expected)?;
+    }
+    Ok(ipld)
+  }
+
+  fn read_digits(&mut self) -> Result<()> {
+    match self.next()? {
+      b'0'..=b'9' => {}
+      byte => return Err(self.unexpected(byte)),
+    }
+    while let Some(b'0'..=b'9') = self.peek() {
+      self.offset += 1;
+    }
+    Ok(())
+  }
+
+  // Numbers with a fraction or an exponent are floats, all others integers
+  fn read_number(&mut self) -> Result<Ipld> {
+    let start = self.offset;
+    let mut float = false;
+    if self.peek() == Some(b'-') {
+      self.offset += 1;
+    }
+    match self.next()? {
+      b'0' => {}
+      b'1'..=b'9' => {
+        while let Some(b'0'..=b'9') = self.peek() {
+          self.offset += 1;
+        }
+      }
+      byte => return Err(self.unexpected(byte)),
+    }
+    if self.peek() == Some(b'.') {
+      self.offset += 1;
+      float = true;
+      self.read_digits()?;
+    }
+    if let Some(b'e' | b'E') = self.peek() {
+      self.offset += 1;
+      float = true;
+      if let Some(b'+' | b'-') = self.peek() {
+        self.offset += 1;
+      }
+      self.read_digits()?;
+    }
+    // Only ASCII digits and signs have been consumed
+    let text = std::str::from_utf8(&self.input[start..self.offset]).unwrap();
+    let out_of_range = NumberOutOfRange { offset: start as u64 };
+    if float {
+      match text.parse::<f64>() {
+        Ok(f) if f.is_finite() => Ok(Ipld::Float(f)),
+        _ => Err(out_of_range),
+      }
+    }
+    else {
+      match text.parse::<i128>() {
+        Ok(i) if in_range(i) => Ok(Ipld::Integer(i)),
+        _ => Err(out_of_range),
+      }
+    }
+  }
+
+  fn read_string(&mut self) -> Result<String> {
+    let start = self.offset;
+    self.expect(b'"')?;
+    let mut bytes = Vec::new();
+    loop {
+      match self.next()? {
+        b'"' => break,
+        b'\\' => {
+          let c = self.read_escape()?;
+          bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+        byte if byte < 0x20 => return Err(self.unexpected(byte)),
+        byte => bytes.push(byte),
+      }
+    }
+    String::from_utf8(bytes).map_err(|_| InvalidUtf8 { offset: start as u64 })
+  }
+
+  fn read_escape(&mut self) -> Result<char> {
+    let start = self.offset as u64 - 1;
+    let c = match self.next()? {
+      b'"' => '"',
+      b'\\' => '\\',
+      b'/' => '/',
+      b'b' => '\u{8}',
+      b'f' => '\u{c}',
+      b'n' => '\n',
+      b'r' => '\r',
+      b't' => '\t',
+      b'u' => {
+        let high = self.read_hex4(start)?;
+        // Characters outside the BMP are escaped as UTF-16 surrogate pairs
+        let code = if (0xd800..0xdc00).contains(&high) {
+          self.expect(b'\\')?;
+          self.expect(b'u')?;
+          let low = self.read_hex4(start)?;
+          if !(0xdc00..0xe000).contains(&low) {
+            return Err(InvalidEscape { offset: start });
+          }
+          0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        }
+        else {
+          high
+        };
+        return char::from_u32(code).ok_or(InvalidEscape { offset: start });
+      }
+      _ => return Err(InvalidEscape { offset: start }),
+    };
+    Ok(c)
+  }
+
+  fn read_hex4(&mut self, start: u64) -> Result<u32> {
+    let mut n = 0;
+    for _ in 0..4 {
+      let digit = (self.next()? as char)
+        .to_digit(16)
+        .ok_or(InvalidEscape { offset: start })?;
+      n = n * 16 + digit;
+    }
+    Ok(n)
+  }
+
+  fn read_array(&mut self) -> Result<Ipld> {
+    self.expect(b'[')?;
+    let mut array = Vec::new();
+    self.skip_whitespace();
+    if self.peek() == Some(b']') {
+      self.offset += 1;
+      return Ok(Ipld::Array(array));
+    }
+    loop {
+      array.push(self.read_ipld()?);
+      self.skip_whitespace();
+      match self.next()? {
+        b',' => continue,
+        b']' => return Ok(Ipld::Array(array)),
+        byte => return Err(self.unexpected(byte)),
+      }
+    }
+  }
+
+  fn read_map(&mut self) -> Result<Ipld> {
+    self.expect(b'{')?;
+    let mut map = BTreeMap::new();
+    // Offset of the value under the `/` key, for errors in links and bytes
+    let mut slash = 0;
+    self.skip_whitespace();
+    if self.peek() == Some(b'}') {
+      self.offset += 1;
+      return Ok(Ipld::Object(map));
+    }
+    loop {
+      self.skip_whitespace();
+      let key_offset = self.offset as u64;
+      let key = self.read_string()?;
+      self.skip_whitespace();
+      self.expect(b':')?;
+      self.skip_whitespace();
+      if key == "/" {
+        slash = self.offset as u64;
+      }
+      let val = self.read_ipld()?;
+      if map.contains_key(&key) {
+        return Err(DuplicateKey { key, offset: key_offset });
+      }
+      map.insert(key, val);
+      self.skip_whitespace();
+      match self.next()? {
+        b',' => continue,
+        b'}' => break,
+        byte => return Err(self.unexpected(byte)),
+      }
+    }
+    match single_entry(&map, "/") {
+      Some(Ipld::String(cid)) => read_link(cid, slash),
+      Some(Ipld::Object(inner)) => match single_entry(inner, "bytes") {
+        Some(Ipld::String(bytes)) => read_bytes(bytes, slash),
+        _ => Ok(Ipld::Object(map)),
+      },
+      _ => Ok(Ipld::Object(map)),
+    }
+  }
+}
+
+fn read_link(s: &str, offset: u64) -> Result<Ipld> {
+  let cid = s.parse().map_err(|source| DagJsonError::Cid { offset, source })?;
+  Ok(Ipld::Link(cid))
+}
+
+fn read_bytes(s: &str, offset: u64) -> Result<Ipld> {
+  let bytes = Base::Base64
+    .decode(&format!("m{}", s))
+    .map_err(|source| DagJsonError::Multibase { offset, source })?;
+  Ok(Ipld::Bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    cid::Cid,
+    dag_json::{
+      deserialize,
+      serialize,
+    },
+    error::{
+      CidError,
+      DagJsonError,
+      MultibaseError,
+      MultihashError,
+      VarintError,
+    },
+    ipld::Ipld,
+    multihash::Multihash,
+  };
+
+  fn decode(s: &str) -> Result<Ipld, DagJsonError> {
+    deserialize(&mut s.as_bytes())
+  }
+
+  fn encode(ipld: &Ipld) -> String {
+    String::from_utf8(serialize(ipld).unwrap()).unwrap()
+  }
+
+  #[test]
+  fn serde_roundtrip() {
+    let cid = Cid::new(1, 0x0129, Multihash::sha3_256(&[1]));
+    let ipld = Ipld::to_object(vec![
+      ("null".into(), Ipld::Null),
+      ("bool".into(), Ipld::Bool(false)),
+      ("int".into(), Ipld::Integer(-(1 << 64))),
+      ("float".into(), Ipld::Float(-0.0)),
+      ("string".into(), Ipld::String("\"\\\u{1}\n\u{1f600}/".into())),
+      ("bytes".into(), Ipld::Bytes(vec![0, 255, 7])),
+      ("link".into(), Ipld::Link(cid)),
+      ("array".into(), Ipld::Array(vec![Ipld::Array(vec![])])),
+      ("slash".into(), Ipld::to_object(vec![("/".into(), Ipld::Null)])),
+    ]);
+    let bytes = serialize(&ipld).unwrap();
+    assert_eq!(deserialize(&mut &bytes[..]).unwrap(), ipld);
+  }
+
+  #[test]
+  fn strings_and_numbers() {
+    assert_eq!(
+      encode(&Ipld::String("a\"b\\c\u{1f}\t\u{e9}".into())),
+      "\"a\\\"b\\\\c\\u001f\\t\u{e9}\""
+    );
+    assert_eq!(encode(&Ipld::Float(1.0)), "1.0");
+    assert_eq!(encode(&Ipld::Float(1e-7)), "1e-7");
+    assert_eq!(decode("1E+2").unwrap(), Ipld::Float(100.0));
+    assert_eq!(decode("-0").unwrap(), Ipld::Integer(0));
+    assert_eq!(
+      decode(r#""😀é\/""#).unwrap(),
+      Ipld::String("\u{1f600}\u{e9}/".into())
+    );
+    assert_eq!(
+      decode(" [ 1 , { \"a\" : true } ]\n").unwrap(),
+      Ipld::Array(vec![
+        Ipld::Integer(1),
+        Ipld::to_object(vec![("a".into(), Ipld::Bool(true))])
+      ])
+    );
+    assert_eq!(
+      serialize(&Ipld::Float(f64::INFINITY)),
+      Err(DagJsonError::NonFiniteFloat)
+    );
+    assert_eq!(
+      serialize(&Ipld::to_object(vec![("/".into(), Ipld::String("x".into()))])),
+      Err(DagJsonError::ReservedKey)
+    );
+  }
+
+  #[test]
+  fn integer_range() {
+    // Same bounds as DAG-CBOR: -2^64 and 2^64 - 1
+    let max = "18446744073709551615";
+    let min = "-18446744073709551616";
+    assert_eq!(decode(max).unwrap(), Ipld::Integer((1 << 64) - 1));
+    assert_eq!(decode(min).unwrap(), Ipld::Integer(-(1 << 64)));
+    assert_eq!(encode(&Ipld::Integer((1 << 64) - 1)), max);
+    assert_eq!(encode(&Ipld::Integer(-(1 << 64))), min);
+    assert_eq!(
+      decode("18446744073709551616"),
+      Err(DagJsonError::NumberOutOfRange { offset: 0 })
+    );
+    assert_eq!(
+      decode("[-18446744073709551617]"),
+      Err(DagJsonError::NumberOutOfRange { offset: 1 })
+    );
+    assert_eq!(
+      serialize(&Ipld::Integer(1 << 64)),
+      Err(DagJsonError::IntegerOutOfRange(1 << 64))
+    );
+    assert_eq!(
+      serialize(&Ipld::Integer(-1 - (1 << 64))),
+      Err(DagJsonError::IntegerOutOfRange(-1 - (1 << 64)))
+    );
+  }
+
+  #[test]
+  fn deserialize_malformed() {
+    assert_eq!(decode(""), Err(DagJsonError::UnexpectedEof { offset: 0 }));
+    assert_eq!(decode("[1,"), Err(DagJsonError::UnexpectedEof { offset: 3 }));
+    assert_eq!(
+      decode("nul!"),
+      Err(DagJsonError::UnexpectedByte { byte: b'!', offset: 3 })
+    );
+    assert_eq!(decode("01"), Err(DagJsonError::TrailingBytes { offset: 1 }));
+    assert_eq!(decode("1."), Err(DagJsonError::UnexpectedEof { offset: 2 }));
+    assert_eq!(
+      decode("1e999"),
+      Err(DagJsonError::NumberOutOfRange { offset: 0 })
+    );
+    assert_eq!(
+      decode("[340282366920938463463374607431768211456]"),
+      Err(DagJsonError::NumberOutOfRange { offset: 1 })
+    );
+    assert_eq!(
+      decode(r#""\x""#),
+      Err(DagJsonError::InvalidEscape { offset: 1 })
+    );
+    assert_eq!(
+      decode(r#""\udc00""#),
+      Err(DagJsonError::InvalidEscape { offset: 1 })
+    );
+    assert_eq!(
+      decode("\"\n\""),
+      Err(DagJsonError::UnexpectedByte { byte: b'\n', offset: 1 })
+    );
+    assert_eq!(
+      deserialize(&mut &b"\"\xff\""[..]),
+      Err(DagJsonError::InvalidUtf8 { offset: 0 })
+    );
+    assert_eq!(
+      decode(r#"{"a":1,"a":2}"#),
+      Err(DagJsonError::DuplicateKey { key: "a".into(), offset: 7 })
+    );
+    assert_eq!(
+      decode(&"[".repeat(129)),
+      Err(DagJsonError::DepthLimit { offset: 128 })
+    );
+    assert_eq!(
+      decode(r#"{"/":"?abc"}"#),
+      Err(DagJsonError::Cid {
+        offset: 5,
+        source: CidError::Multibase(MultibaseError::UnknownCode('?'))
+      })
+    );
+    assert_eq!(
+      decode(r#"{"/":"bafyre"}"#),
+      Err(DagJsonError::Cid {
+        offset: 5,
+        source: CidError::Multihash(MultihashError::Size(
+          VarintError::UnexpectedEof { offset: 0 }
+        ))
+      })
+    );
+    assert_eq!(
+      decode(r#"{"/":{"bytes":"AA!"}}"#),
+      Err(DagJsonError::Multibase {
+        offset: 5,
+        source: MultibaseError::Digit { digit: '!', offset: 3 }
+      })
+    );
+  }
+}
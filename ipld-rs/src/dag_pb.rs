@@ -0,0 +1,511 @@
+//! DAG-PB (multicodec `0x70`) encoding of [`Ipld`].
+//!
+//! DAG-PB blocks are `PBNode` protobuf messages. In the IPLD data model a node
+//! is a map with a `Links` list and an optional `Data` bytes field, and every
+//! link is a map with a `Hash` link and optional `Name` and `Tsize` fields.
+//! Both directions follow the DAG-PB spec strictly: links are written before
+//! the data, link fields in field number order, and anything else is rejected.
+
+use std::{
+  collections::BTreeMap,
+  io::Read,
+};
+
+use crate::{
+  cid::Cid,
+  error::{
+    CidError,
+    DagPbError::{
+      self,
+      *,
+    },
+  },
+  ipld::Ipld,
+};
+
+type Result<T> = std::result::Result<T, DagPbError>;
+
+// Protobuf wire types used by DAG-PB
+const VARINT: u64 = 0;
+const LEN: u64 = 2;
+
+/// A `PBNode` message.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct PbNode {
+  pub links: Vec<PbLink>,
+  pub data: Option<Vec<u8>>,
+}
+
+/// A `PBLink` message.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PbLink {
+  pub hash: Cid,
+  pub name: Option<String>,
+  pub tsize: Option<u64>,
+}
+
+impl PbNode {
+  /// Encodes the node, failing if its links are not sorted by name.
+  pub fn to_bytes(&self) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    for (index, pair) in self.links.windows(2).enumerate() {
+      if pair[0].name_bytes() > pair[1].name_bytes() {
+        return Err(UnsortedLinks { index: index + 1 });
+      }
+    }
+    for link in &self.links {
+      put_len_delimited(&mut bytes, 2, &link.to_bytes()?);
+    }
+    if let Some(data) = &self.data {
+      put_len_delimited(&mut bytes, 1, data);
+    }
+    Ok(bytes)
+  }
+
+  /// Decodes a node that makes up all of `bytes`.
+  pub fn from_bytes(bytes: &[u8]) -> Result<PbNode> {
+    let mut r = Reader { input: bytes, offset: 0 };
+    let mut node = PbNode::default();
+    while !r.at_eof() {
+      let offset = r.offset as u64;
+      let (field, wire_type) = r.read_key()?;
+      match field {
+        1 | 2 if wire_type != LEN => {
+          return Err(WireType { field, wire_type, offset });
+        }
+        // Every link has to come before the data
+        1 | 2 if node.data.is_some() => {
+          return Err(FieldOrder { field, offset });
+        }
+        1 => node.data = Some(r.read_len_delimited()?.rest().to_vec()),
+        2 => node.links.push(PbLink::read(&mut r.read_len_delimited()?)?),
+        _ => return Err(UnknownField { field, offset }),
+      }
+    }
+    Ok(node)
+  }
+}
+
+impl PbLink {
+  fn name_bytes(&self) -> &[u8] {
+    self.name.as_ref().map_or(&[], |name| name.as_bytes())
+  }
+
+  fn to_bytes(&self) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    let hash = self.hash.to_bytes().map_err(InvalidLink)?;
+    put_len_delimited(&mut bytes, 1, &hash);
+    if let Some(name) = &self.name {
+      put_len_delimited(&mut bytes, 2, name.as_bytes());
+    }
+    if let Some(tsize) = self.tsize {
+      put_varint(&mut bytes, (3 << 3) | VARINT);
+      put_varint(&mut bytes, tsize);
+    }
+    Ok(bytes)
+  }
+
+  // Fields must appear at most once and in field number order
+  fn read(r: &mut Reader) -> Result<PbLink> {
+    let start = r.offset as u64;
+    let mut last = 0;
+    let mut hash = None;
+    let mut name = None;
+    let mut tsize = None;
+    while !r.at_eof() {
+      let offset = r.offset as u64;
+      let (field, wire_type) = r.read_key()?;
+      let expected = match field {
+        1 | 2 => LEN,
+        3 => VARINT,
+        _ => return Err(UnknownField { field, offset }),
+      };
+      if wire_type != expected {
+        return Err(WireType { field, wire_type, offset });
+      }
+      if field <= last {
+        return Err(FieldOrder { field, offset });
+      }
+      last = field;
+      match field {
+        1 => {
+          let bytes = r.read_len_delimited()?;
+          let offset = bytes.offset as u64;
+          let cid_error = |source| DagPbError::Cid { offset, source };
+          // The whole field must be the CID, or re-encoding would drop bytes
+          let mut rest = bytes.rest();
+          let cid = Cid::from_bytes(&mut rest).map_err(cid_error)?;
+          if !rest.is_empty() {
+            return Err(cid_error(CidError::TrailingBytes));
+          }
+          hash = Some(cid);
+        }
+        2 => {
+          let bytes = r.read_len_delimited()?;
+          let offset = bytes.offset as u64;
+          name = Some(
+            String::from_utf8(bytes.rest().to_vec())
+              .map_err(|_| InvalidUtf8 { offset })?,
+          );
+        }
+        _ => tsize = Some(r.read_varint()?),
+      }
+    }
+    let hash = hash.ok_or(MissingHash { offset: start })?;
+    Ok(PbLink { hash, name, tsize })
+  }
+}
+
+// Protobuf varints use all 64 bits, so unlike the multiformats varint they
+// can take ten bytes
+fn put_varint(bytes: &mut Vec<u8>, mut n: u64) {
+  while n >= 0x80 {
+    bytes.push((n as u8) | 0x80);
+    n >>= 7;
+  }
+  bytes.push(n as u8);
+}
+
+fn put_len_delimited(bytes: &mut Vec<u8>, field: u64, value: &[u8]) {
+  put_varint(bytes, (field << 3) | LEN);
+  put_varint(bytes, value.len() as u64);
+  bytes.extend(value);
+}
+
+// Reads protobuf fields out of `input[offset..]`. Nested messages get a reader
+// over the same input cut at the end of the message, so that offsets are
+// always relative to the start of the block
+struct Reader<'a> {
+  input: &'a [u8],
+  offset: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn at_eof(&self) -> bool { self.offset >= self.input.len() }
+
+  fn rest(&self) -> &'a [u8] { &self.input[self.offset..] }
+
+  // Protobuf varints are at most 10 bytes long
+  fn read_varint(&mut self) -> Result<u64> {
+    let start = self.offset as u64;
+    let mut n: u64 = 0;
+    for i in 0..10 {
+      let byte = *self
+        .input
+        .get(self.offset)
+        .ok_or(UnexpectedEof { offset: self.offset as u64 })?;
+      self.offset += 1;
+      if i == 9 && byte > 1 {
+        return Err(VarintOverflow { offset: start });
+      }
+      n |= u64::from(byte & 0x7f) << (i * 7);
+      if byte & 0x80 == 0 {
+        return Ok(n);
+      }
+    }
+    Err(VarintOverflow { offset: start })
+  }
+
+  fn read_key(&mut self) -> Result<(u64, u64)> {
+    let key = self.read_varint()?;
+    Ok((key >> 3, key & 0x7))
+  }
+
+  fn read_len_delimited(&mut self) -> Result<Reader<'a>> {
+    let len = self.read_varint()?;
+    let end = (self.offset as u64)
+      .checked_add(len)
+      .filter(|end| *end <= self.input.len() as u64)
+      .ok_or(UnexpectedEof { offset: self.input.len() as u64 })?;
+    let nested =
+      Reader { input: &self.input[..end as usize], offset: self.offset };
+    self.offset = end as usize;
+    Ok(nested)
+  }
+}
+
+impl From<PbNode> for Ipld {
+  fn from(node: PbNode) -> Self {
+    let mut map = BTreeMap::new();
+    let links = node.links.into_iter().map(Ipld::from).collect();
+    map.insert("Links".to_string(), Ipld::Array(links));
+    if let Some(data) = node.data {
+      map.insert("Data".to_string(), Ipld::Bytes(data));
+    }
+    Ipld::Object(map)
+  }
+}
+
+impl From<PbLink> for Ipld {
+  fn from(link: PbLink) -> Self {
+    let mut map = BTreeMap::new();
+    map.insert("Hash".to_string(), Ipld::Link(link.hash));
+    if let Some(name) = link.name {
+      map.insert("Name".to_string(), Ipld::String(name));
+    }
+    if let Some(tsize) = link.tsize {
+      map.insert("Tsize".to_string(), Ipld::Integer(tsize.into()));
+    }
+    Ipld::Object(map)
+  }
+}
+
+fn check_keys(map: &BTreeMap<String, Ipld>, allowed: &[&str]) -> Result<()> {
+  match map.keys().find(|key| !allowed.contains(&key.as_str())) {
+    Some(key) => Err(UnexpectedKey(key.clone())),
+    None => Ok(()),
+  }
+}
+
+impl TryFrom<&Ipld> for PbNode {
+  type Error = DagPbError;
+
+  fn try_from(ipld: &Ipld) -> Result<Self> {
+    let map = match ipld {
+      Ipld::Object(map) => map,
+      _ => return Err(NotANode),
+    };
+    check_keys(map, &["Data", "Links"])?;
+    let links = match map.get("Links") {
+      Some(Ipld::Array(links)) => links,
+      Some(_) => return Err(InvalidField("Links")),
+      None => return Err(NotANode),
+    };
+    let data = match map.get("Data") {
+      Some(Ipld::Bytes(data)) => Some(data.clone()),
+      Some(_) => return Err(InvalidField("Data")),
+      None => None,
+    };
+    let links = links.iter().map(PbLink::try_from).collect::<Result<_>>()?;
+    Ok(PbNode { links, data })
+  }
+}
+
+impl TryFrom<&Ipld> for PbLink {
+  type Error = DagPbError;
+
+  fn try_from(ipld: &Ipld) -> Result<Self> {
+    let map = match ipld {
+      Ipld::Object(map) => map,
+      _ => return Err(NotALink),
+    };
+    check_keys(map, &["Hash", "Name", "Tsize"])?;
+    let hash = match map.get("Hash") {
+      Some(Ipld::Link(cid)) => cid.clone(),
+      Some(_) => return Err(InvalidField("Hash")),
+      None => return Err(NotALink),
+    };
+    let name = match map.get("Name") {
+      Some(Ipld::String(name)) => Some(name.clone()),
+      Some(_) => return Err(InvalidField("Name")),
+      None => None,
+    };
+    let tsize = match map.get("Tsize") {
+      Some(Ipld::Integer(tsize)) => {
+        Some(u64::try_from(*tsize).map_err(|_| InvalidField("Tsize"))?)
+      }
+      Some(_) => return Err(InvalidField("Tsize")),
+      None => None,
+    };
+    Ok(PbLink { hash, name, tsize })
+  }
+}
+
+/// Encodes a DAG-PB shaped `ipld` value.
+pub fn serialize(ipld: &Ipld) -> Result<Vec<u8>> {
+  PbNode::try_from(ipld)?.to_bytes()
+}
+
+/// Decodes a DAG-PB block that makes up all of `r`.
+pub fn deserialize<R: Read>(r: &mut R) -> Result<Ipld> {
+  let mut bytes = Vec::new();
+  r.read_to_end(&mut bytes)
+    .map_err(|e| Io { offset: bytes.len() as u64, kind: e.kind() })?;
+  Ok(PbNode::from_bytes(&bytes)?.into())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    cid::Cid,
+    dag_pb::{
+      deserialize,
+      serialize,
+      PbLink,
+      PbNode,
+    },
+    error::{
+      CidError,
+      DagPbError,
+      MultihashError,
+      VarintError,
+    },
+    ipld::Ipld,
+    test_util::hex,
+  };
+
+  const CID: &str =
+    "01711220f88bc853804cf294fe417e4fa83028689fcdb1b1592c5102e1474dbc200fab8b";
+
+  fn link(name: Option<&str>, tsize: Option<u64>) -> PbLink {
+    let hash = Cid::try_from(hex(CID)).unwrap();
+    PbLink { hash, name: name.map(Into::into), tsize }
+  }
+
+  #[test]
+  fn dag_pb_vectors() {
+    let vectors = [
+      (PbNode::default(), String::new()),
+      (PbNode { links: vec![], data: Some(vec![]) }, "0a00".into()),
+      // An empty UnixFS directory
+      (PbNode { links: vec![], data: Some(vec![8, 1]) }, "0a020801".into()),
+      (
+        PbNode { links: vec![link(None, None)], data: None },
+        format!("12260a24{}", CID),
+      ),
+      (
+        PbNode {
+          links: vec![link(Some("a"), Some(300))],
+          data: Some(vec![1, 2, 3]),
+        },
+        format!("122c0a24{}12016118ac020a03010203", CID),
+      ),
+    ];
+    for (node, bytes) in vectors {
+      assert_eq!(node.to_bytes().unwrap(), hex(&bytes));
+      assert_eq!(PbNode::from_bytes(&hex(&bytes)).unwrap(), node);
+      let ipld = Ipld::from(node);
+      assert_eq!(serialize(&ipld).unwrap(), hex(&bytes));
+      assert_eq!(deserialize(&mut &hex(&bytes)[..]).unwrap(), ipld);
+    }
+  }
+
+  #[test]
+  fn data_model_shape() {
+    let ipld = Ipld::from(PbNode {
+      links: vec![link(Some("a"), Some(3))],
+      data: Some(vec![1]),
+    });
+    let hash = Ipld::Link(Cid::try_from(hex(CID)).unwrap());
+    assert_eq!(
+      ipld,
+      Ipld::to_object(vec![
+        ("Data".into(), Ipld::Bytes(vec![1])),
+        (
+          "Links".into(),
+          Ipld::Array(vec![Ipld::to_object(vec![
+            ("Hash".into(), hash.clone()),
+            ("Name".into(), Ipld::String("a".into())),
+            ("Tsize".into(), Ipld::Integer(3)),
+          ])])
+        ),
+      ])
+    );
+    let node = |entries| serialize(&Ipld::to_object(entries));
+    assert_eq!(serialize(&Ipld::Null), Err(DagPbError::NotANode));
+    assert_eq!(node(vec![]), Err(DagPbError::NotANode));
+    assert_eq!(
+      node(vec![
+        ("Links".into(), Ipld::Array(vec![])),
+        ("x".into(), Ipld::Null)
+      ]),
+      Err(DagPbError::UnexpectedKey("x".into()))
+    );
+    assert_eq!(
+      node(vec![
+        ("Links".into(), Ipld::Array(vec![])),
+        ("Data".into(), Ipld::Null)
+      ]),
+      Err(DagPbError::InvalidField("Data"))
+    );
+    assert_eq!(
+      node(vec![(
+        "Links".into(),
+        Ipld::Array(vec![Ipld::to_object(vec![
+          ("Hash".into(), hash),
+          ("Tsize".into(), Ipld::Integer(-1)),
+        ])])
+      )]),
+      Err(DagPbError::InvalidField("Tsize"))
+    );
+    assert_eq!(
+      node(vec![("Links".into(), Ipld::Array(vec![Ipld::Null]))]),
+      Err(DagPbError::NotALink)
+    );
+    let unsorted = PbNode {
+      links: vec![
+        link(Some("b"), None),
+        link(None, None),
+        link(Some("a"), None),
+      ],
+      data: None,
+    };
+    assert_eq!(
+      unsorted.to_bytes(),
+      Err(DagPbError::UnsortedLinks { index: 1 })
+    );
+  }
+
+  #[test]
+  fn deserialize_malformed() {
+    let decode = |s: &str| PbNode::from_bytes(&hex(s));
+    // Data before links
+    assert_eq!(
+      decode(&format!("0a0012260a24{}", CID)),
+      Err(DagPbError::FieldOrder { field: 2, offset: 2 })
+    );
+    assert_eq!(
+      decode("0a000a00"),
+      Err(DagPbError::FieldOrder { field: 1, offset: 2 })
+    );
+    assert_eq!(
+      decode("1a00"),
+      Err(DagPbError::UnknownField { field: 3, offset: 0 })
+    );
+    assert_eq!(
+      decode("0800"),
+      Err(DagPbError::WireType { field: 1, wire_type: 0, offset: 0 })
+    );
+    assert_eq!(decode("0a05"), Err(DagPbError::UnexpectedEof { offset: 2 }));
+    assert_eq!(decode("0a"), Err(DagPbError::UnexpectedEof { offset: 1 }));
+    assert_eq!(
+      decode("0affffffffffffffffff7f"),
+      Err(DagPbError::VarintOverflow { offset: 1 })
+    );
+    // Name before hash
+    assert_eq!(
+      decode(&format!("122912016e0a24{}", CID)),
+      Err(DagPbError::FieldOrder { field: 1, offset: 5 })
+    );
+    assert_eq!(decode("12021801"), Err(DagPbError::MissingHash { offset: 2 }));
+    assert_eq!(
+      decode("12020a00"),
+      Err(DagPbError::Cid {
+        offset: 4,
+        source: CidError::Version(VarintError::UnexpectedEof { offset: 0 })
+      })
+    );
+    assert_eq!(
+      decode("12050a0301711212"),
+      Err(DagPbError::Cid {
+        offset: 4,
+        source: CidError::Multihash(MultihashError::Size(
+          VarintError::UnexpectedEof { offset: 0 }
+        ))
+      })
+    );
+    // Hash field with a byte after the CID
+    assert_eq!(
+      decode(&format!("12270a25{}00", CID)),
+      Err(DagPbError::Cid { offset: 4, source: CidError::TrailingBytes })
+    );
+    assert_eq!(
+      decode(&format!("122a0a24{}1202ffff", CID)),
+      Err(DagPbError::InvalidUtf8 { offset: 42 })
+    );
+    assert_eq!(
+      decode(&format!("122a0a24{}1a020000", CID)),
+      Err(DagPbError::WireType { field: 3, wire_type: 2, offset: 40 })
+    );
+  }
+}
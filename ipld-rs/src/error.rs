@@ -1,10 +1,347 @@
-// TODO: Add descriptive errors with thiserror
-
-// use std::fmt;
-// use thiserror::Error;
-//
-//#[derive(Error, Debug)]
-// pub enum CidError {
-//  #[error("Byte Error")]
-//  ByteError,
-//}
+//! Errors returned by the codecs.
+//!
+//! Every decoder in the crate returns one of the specific error types below;
+//! [`enum@Error`] wraps all of them for callers that do not care which layer
+//! failed.
+
+use crate::cid::Cid;
+
+use std::{
+  fmt,
+  io,
+};
+use thiserror::Error;
+
+/// Errors produced while decoding an unsigned varint.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum VarintError {
+  #[error("Unexpected end of input in varint at byte {offset}")]
+  UnexpectedEof { offset: usize },
+  #[error("Varint overflow at byte {offset}")]
+  Overflow { offset: usize },
+  #[error("Non-minimal varint ending at byte {offset}")]
+  NotMinimal { offset: usize },
+  #[error("Value {0} is above 2^63 - 1, the largest varint")]
+  TooLarge(u64),
+  #[error("I/O error while reading varint: {0:?}")]
+  Io(io::ErrorKind),
+}
+
+/// Errors produced while decoding a multihash.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MultihashError {
+  #[error("Invalid multihash code: {0}")]
+  Code(VarintError),
+  #[error("Invalid multihash size: {0}")]
+  Size(VarintError),
+  #[error("Digest size {size} is too large for multihash code {code:#x}")]
+  InvalidSize { code: u64, size: u64 },
+  #[error(
+    "Multihash digest truncated: expected {expected} bytes, found {found}"
+  )]
+  Truncated { expected: u64, found: u64 },
+  #[error("I/O error while reading multihash: {0:?}")]
+  Io(io::ErrorKind),
+}
+
+/// Errors produced while decoding a CID.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CidError {
+  #[error("Invalid CID version: {0}")]
+  Version(VarintError),
+  #[error("Unsupported CID version {0}")]
+  UnsupportedVersion(u64),
+  #[error("CIDv0 must be the sha2-256 hash of a dag-pb block")]
+  InvalidV0,
+  #[error("Invalid CID codec: {0}")]
+  Codec(VarintError),
+  #[error("Invalid CID multihash: {0}")]
+  Multihash(#[from] MultihashError),
+  #[error("Invalid CID string: {0}")]
+  Multibase(#[from] MultibaseError),
+  #[error("Unexpected bytes after the end of the CID")]
+  TrailingBytes,
+}
+
+/// Errors produced while decoding a multibase string.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MultibaseError {
+  #[error("Empty multibase string")]
+  Empty,
+  #[error("Unknown multibase code `{0}`")]
+  UnknownCode(char),
+  #[error("Invalid multibase code: expected `{expected}`, found `{found}`")]
+  Code { expected: char, found: char },
+  #[error("Invalid multibase digit `{digit}` at character {offset}")]
+  Digit { digit: char, offset: usize },
+  #[error("Unused non-zero or excess bits in the digit at character {offset}")]
+  TrailingBits { offset: usize },
+  #[error("Expected {expected} padding characters, found {found}")]
+  Padding { expected: usize, found: usize },
+  #[error("Invalid multibase alphabet: {0}")]
+  InvalidAlphabet(&'static str),
+}
+
+/// Errors produced while encoding or decoding DAG-CBOR. Offsets are counted in
+/// bytes from the start of the input.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DagCborError {
+  #[error("Integer {0} is outside the range representable in CBOR")]
+  IntegerOutOfRange(i128),
+  #[error("NaN and infinite floats are not allowed in DAG-CBOR")]
+  NonFiniteFloat,
+  #[error("Link cannot be encoded: {0}")]
+  InvalidLink(CidError),
+  #[error("Unexpected end of input at byte {offset}")]
+  UnexpectedEof { offset: u64 },
+  #[error("Unknown CBOR tag `{tag}` at byte {offset}")]
+  UnknownCborTag { tag: u64, offset: u64 },
+  #[error("Unexpected CBOR code `{code:#04x}` at byte {offset}")]
+  UnexpectedCborCode { code: u8, offset: u64 },
+  #[error("Map key at byte {offset} is not a string")]
+  NonStringKey { offset: u64 },
+  #[error("Non-minimal integer or length encoding at byte {offset}")]
+  NonMinimalInteger { offset: u64 },
+  #[error("Map key at byte {offset} is out of canonical order")]
+  UnsortedKeys { offset: u64 },
+  #[error("Duplicate map key `{key}` at byte {offset}")]
+  DuplicateKey { key: String, offset: u64 },
+  #[error("Indefinite length item at byte {offset}")]
+  IndefiniteLength { offset: u64 },
+  #[error("Undefined value at byte {offset}")]
+  Undefined { offset: u64 },
+  #[error("Non-canonical float at byte {offset}")]
+  NonCanonicalFloat { offset: u64 },
+  #[error("Trailing bytes after the end of the value at byte {offset}")]
+  TrailingBytes { offset: u64 },
+  #[error("Nesting depth limit exceeded at byte {offset}")]
+  DepthLimit { offset: u64 },
+  #[error("Allocation limit exceeded at byte {offset}")]
+  AllocationLimit { offset: u64 },
+  #[error("Collection of {len} elements at byte {offset} exceeds the limit")]
+  CollectionLimit { len: u64, offset: u64 },
+  #[error("String of {len} bytes at byte {offset} exceeds the limit")]
+  StringLimit { len: u64, offset: u64 },
+  #[error("Invalid UTF-8 string at byte {offset}")]
+  InvalidUtf8 { offset: u64 },
+  #[error("CID length too large at byte {offset}")]
+  CidLenOutofRange { offset: u64 },
+  #[error("Invalid CID prefix at byte {offset}")]
+  CidPrefix { offset: u64 },
+  #[error("Invalid CID at byte {offset}: {source}")]
+  Cid { offset: u64, source: CidError },
+  #[error("I/O error at byte {offset}: {kind:?}")]
+  Io { offset: u64, kind: io::ErrorKind },
+}
+
+/// Errors produced while encoding or decoding DAG-JSON. Offsets are counted in
+/// bytes from the start of the input.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DagJsonError {
+  #[error("NaN and infinite floats are not allowed in DAG-JSON")]
+  NonFiniteFloat,
+  #[error("Integer {0} is outside the DAG-JSON range -2^64..2^64")]
+  IntegerOutOfRange(i128),
+  #[error("Link cannot be encoded: {0}")]
+  InvalidLink(CidError),
+  #[error("Map with a single `/` key would be read back as a link or bytes")]
+  ReservedKey,
+  #[error("Unexpected end of input at byte {offset}")]
+  UnexpectedEof { offset: u64 },
+  #[error("Unexpected byte `{byte:#04x}` at byte {offset}")]
+  UnexpectedByte { byte: u8, offset: u64 },
+  #[error("Number at byte {offset} is out of range")]
+  NumberOutOfRange { offset: u64 },
+  #[error("Invalid escape sequence at byte {offset}")]
+  InvalidEscape { offset: u64 },
+  #[error("Invalid UTF-8 string at byte {offset}")]
+  InvalidUtf8 { offset: u64 },
+  #[error("Duplicate map key `{key}` at byte {offset}")]
+  DuplicateKey { key: String, offset: u64 },
+  #[error("Nesting depth limit exceeded at byte {offset}")]
+  DepthLimit { offset: u64 },
+  #[error("Trailing bytes after the end of the value at byte {offset}")]
+  TrailingBytes { offset: u64 },
+  #[error("Invalid multibase string at byte {offset}: {source}")]
+  Multibase { offset: u64, source: MultibaseError },
+  #[error("Invalid CID at byte {offset}: {source}")]
+  Cid { offset: u64, source: CidError },
+  #[error("I/O error at byte {offset}: {kind:?}")]
+  Io { offset: u64, kind: io::ErrorKind },
+}
+
+/// Errors produced while encoding or decoding DAG-PB. Decoding offsets are
+/// counted in bytes from the start of the block.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DagPbError {
+  #[error("DAG-PB node must be a map with `Links` and optional `Data`")]
+  NotANode,
+  #[error(
+    "DAG-PB link must be a map with `Hash` and optional `Name`, `Tsize`"
+  )]
+  NotALink,
+  #[error("Unexpected key `{0}` in DAG-PB data")]
+  UnexpectedKey(String),
+  #[error("Invalid value for the `{0}` field")]
+  InvalidField(&'static str),
+  #[error("Links must be sorted by name, link {index} is out of order")]
+  UnsortedLinks { index: usize },
+  #[error("Link cannot be encoded: {0}")]
+  InvalidLink(CidError),
+  #[error("Unexpected end of input at byte {offset}")]
+  UnexpectedEof { offset: u64 },
+  #[error("Protobuf varint overflow at byte {offset}")]
+  VarintOverflow { offset: u64 },
+  #[error("Field {field} has wire type {wire_type} at byte {offset}")]
+  WireType { field: u64, wire_type: u64, offset: u64 },
+  #[error("Unknown field {field} at byte {offset}")]
+  UnknownField { field: u64, offset: u64 },
+  #[error("Field {field} is duplicated or out of order at byte {offset}")]
+  FieldOrder { field: u64, offset: u64 },
+  #[error("Link without a hash at byte {offset}")]
+  MissingHash { offset: u64 },
+  #[error("Invalid UTF-8 string at byte {offset}")]
+  InvalidUtf8 { offset: u64 },
+  #[error("Invalid CID at byte {offset}: {source}")]
+  Cid { offset: u64, source: CidError },
+  #[error("I/O error at byte {offset}: {kind:?}")]
+  Io { offset: u64, kind: io::ErrorKind },
+}
+
+/// Errors produced when picking or running a codec by its multicodec code.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+  #[error("No codec registered for multicodec {0:#x}")]
+  UnknownCodec(u64),
+  #[error("The raw codec can only encode bytes")]
+  NotBytes,
+  #[error("{0}")]
+  Custom(String),
+}
+
+/// Errors produced while checking a block against its CID.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum BlockError {
+  #[error("Cannot compute multihash code {0:#x}")]
+  UnsupportedHash(u64),
+  #[error("Block data does not match the hash in CID {0}")]
+  HashMismatch(Cid),
+  #[error("Truncated digest of {0} bytes is too short to verify a block")]
+  DigestTooShort(u64),
+}
+
+/// Errors produced while reading or writing a CAR archive. Offsets count
+/// bytes from the start of the archive.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CarError {
+  #[error("Invalid CAR header: {0}")]
+  Header(DagCborError),
+  #[error("Invalid CAR header: {0}")]
+  InvalidHeader(&'static str),
+  #[error("Unsupported CAR version {0}")]
+  UnsupportedVersion(u64),
+  #[error("Missing CARv2 pragma")]
+  InvalidPragma,
+  #[error("Unknown CAR index format {0:#x}")]
+  UnknownIndex(u64),
+  #[error("Invalid CAR index at byte {offset}: {reason}")]
+  InvalidIndex { reason: &'static str, offset: u64 },
+  #[error("Invalid CAR length prefix at byte {offset}: {source}")]
+  Length { offset: u64, source: VarintError },
+  #[error("CAR header of {len} bytes exceeds the limit")]
+  HeaderLimit { len: u64 },
+  #[error("CAR section of {len} bytes at byte {offset} exceeds the limit")]
+  SectionLimit { len: u64, offset: u64 },
+  #[error("Unexpected end of CAR section at byte {offset}")]
+  UnexpectedEof { offset: u64 },
+  #[error("Invalid CID in CAR section at byte {offset}: {source}")]
+  Cid { offset: u64, source: CidError },
+  #[error("I/O error at byte {offset} of the CAR: {kind:?}")]
+  Io { offset: u64, kind: io::ErrorKind },
+}
+
+/// Errors produced while looking up a path in IPLD data. `path` is the part
+/// of the path resolved before the failing segment.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+  #[error("No key `{key}` in the map at `{path}`")]
+  MissingKey { key: String, path: String },
+  #[error("Index {index} is out of range for the list of {len} at `{path}`")]
+  IndexOutOfRange { index: usize, len: usize, path: String },
+  #[error("`{segment}` is not a valid index for the list at `{path}`")]
+  InvalidIndex { segment: String, path: String },
+  #[error("Cannot look up `{segment}` in the {kind} at `{path}`")]
+  NotTraversable { segment: String, kind: &'static str, path: String },
+  #[error("The value at `{path}` is a link to {cid}")]
+  Link { cid: Cid, path: String },
+}
+
+/// Errors produced while following links between blocks.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TraversalError {
+  #[error("Block {0} could not be found")]
+  MissingBlock(Cid),
+}
+
+/// Errors produced by the block stores.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum StoreError {
+  #[error("Block store uses sharding {0:?}, expected {1:?}")]
+  Sharding(String, String),
+  #[error("I/O error in block store: {0:?}")]
+  Io(io::ErrorKind),
+}
+
+/// Errors produced while converting between [`crate::ipld::Ipld`] and Rust
+/// types through serde.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SerdeError(String);
+
+impl fmt::Display for SerdeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Serde error: {}", self.0)
+  }
+}
+
+impl serde::de::Error for SerdeError {
+  fn custom<T: fmt::Display>(msg: T) -> Self { Self(msg.to_string()) }
+}
+
+impl serde::ser::Error for SerdeError {
+  fn custom<T: fmt::Display>(msg: T) -> Self { Self(msg.to_string()) }
+}
+
+impl serde::ser::StdError for SerdeError {}
+
+/// Any error produced by this crate.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+  #[error(transparent)]
+  Varint(#[from] VarintError),
+  #[error(transparent)]
+  Multihash(#[from] MultihashError),
+  #[error(transparent)]
+  Cid(#[from] CidError),
+  #[error(transparent)]
+  Multibase(#[from] MultibaseError),
+  #[error(transparent)]
+  DagCbor(#[from] DagCborError),
+  #[error(transparent)]
+  DagJson(#[from] DagJsonError),
+  #[error(transparent)]
+  DagPb(#[from] DagPbError),
+  #[error(transparent)]
+  Codec(#[from] CodecError),
+  #[error(transparent)]
+  Block(#[from] BlockError),
+  #[error(transparent)]
+  Store(#[from] StoreError),
+  #[error(transparent)]
+  Car(#[from] CarError),
+  #[error(transparent)]
+  Traversal(#[from] TraversalError),
+  #[error(transparent)]
+  Path(#[from] PathError),
+  #[error(transparent)]
+  Serde(#[from] SerdeError),
+}
@@ -1,6 +1,9 @@
 //! Ipld representation.
 
-use crate::cid::Cid;
+use crate::{
+  cid::Cid,
+  error::PathError,
+};
 use std::collections::BTreeMap;
 
 use std::fmt;
@@ -12,8 +15,11 @@ pub enum Ipld {
   Null,
   /// Represents a boolean value.
   Bool(bool),
-  /// Represents an integer.
-  Number(u64),
+  /// Represents an integer in the range `-2^64..2^64`, which covers both
+  /// `i64` and `u64`.
+  Integer(i128),
+  /// Represents a floating point value.
+  Float(f64),
   /// Represents an UTF-8 string.
   String(String),
   /// Represents a sequence of bytes.
@@ -34,6 +40,104 @@ impl Ipld {
     }
     Ipld::Object(res)
   }
+
+  /// Returns every link in the value, depth first and with map entries in
+  /// key order.
+  pub fn links(&self) -> Vec<&Cid> {
+    let mut links = vec![];
+    let mut stack = vec![self];
+    while let Some(ipld) = stack.pop() {
+      match ipld {
+        Ipld::Link(cid) => links.push(cid),
+        Ipld::Array(items) => stack.extend(items.iter().rev()),
+        Ipld::Object(map) => stack.extend(map.values().rev()),
+        _ => (),
+      }
+    }
+    links
+  }
+
+  /// Names the kind of the value, as in the IPLD data model.
+  pub fn kind(&self) -> &'static str {
+    match self {
+      Ipld::Null => "null",
+      Ipld::Bool(_) => "boolean",
+      Ipld::Integer(_) => "integer",
+      Ipld::Float(_) => "float",
+      Ipld::String(_) => "string",
+      Ipld::Bytes(_) => "bytes",
+      Ipld::Array(_) => "list",
+      Ipld::Object(_) => "map",
+      Ipld::Link(_) => "link",
+    }
+  }
+
+  /// Looks up one path segment: a key in a map or an index in a list. `path`
+  /// is where `self` was found, for errors.
+  pub(crate) fn get_segment(
+    &self,
+    segment: &str,
+    path: &str,
+  ) -> Result<&Ipld, PathError> {
+    match self {
+      Ipld::Object(map) => map.get(segment).ok_or_else(|| {
+        PathError::MissingKey { key: segment.into(), path: path.into() }
+      }),
+      Ipld::Array(items) => {
+        // Indices are written in decimal without leading zeros
+        let canonical = segment == "0"
+          || (!segment.starts_with('0')
+            && !segment.is_empty()
+            && segment.bytes().all(|b| b.is_ascii_digit()));
+        let index =
+          segment.parse::<usize>().ok().filter(|_| canonical).ok_or_else(
+            || PathError::InvalidIndex {
+              segment: segment.into(),
+              path: path.into(),
+            },
+          )?;
+        items.get(index).ok_or_else(|| PathError::IndexOutOfRange {
+          index,
+          len: items.len(),
+          path: path.into(),
+        })
+      }
+      Ipld::Link(cid) => {
+        Err(PathError::Link { cid: cid.clone(), path: path.into() })
+      }
+      ipld => Err(PathError::NotTraversable {
+        segment: segment.into(),
+        kind: ipld.kind(),
+        path: path.into(),
+      }),
+    }
+  }
+
+  /// Looks up a `/` separated path of map keys and list indices within the
+  /// value, without following links. Empty segments are ignored, so `""`
+  /// and `"/"` name the value itself.
+  pub fn get_path(&self, path: &str) -> Result<&Ipld, PathError> {
+    let mut ipld = self;
+    let mut resolved = String::new();
+    for segment in path_segments(path) {
+      ipld = ipld.get_segment(segment, &resolved)?;
+      push_segment(&mut resolved, segment);
+    }
+    Ok(ipld)
+  }
+}
+
+/// Splits a path into its non-empty segments.
+pub(crate) fn path_segments(path: &str) -> impl Iterator<Item = &str> {
+  path.split('/').filter(|segment| !segment.is_empty())
+}
+
+/// Appends `segment` to the path `path`.
+pub(crate) fn push_segment(path: &mut String, segment: &str) {
+  if !path.is_empty() {
+    path.push('/');
+  }
+  path.push_str(segment);
 }
 
 impl fmt::Display for Ipld {
@@ -41,7 +145,8 @@ impl fmt::Display for Ipld {
     let output = match self {
       Ipld::Null => "Ipld.null".to_string(),
       Ipld::Bool(b) => format!("(Ipld.bool {b})"),
-      Ipld::Number(n) => format!("(Ipld.number {n})"),
+      Ipld::Integer(n) => format!("(Ipld.integer {n})"),
+      Ipld::Float(n) => format!("(Ipld.float {n})"),
       Ipld::String(s) => format!("(Ipld.string {s})"),
       Ipld::Bytes(b) => format!("(Ipld.bytes {:?})", b),
       Ipld::Array(obs) => {
@@ -60,3 +165,76 @@ impl fmt::Display for Ipld {
     write!(f, "{}", output)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    cid::Cid,
+    error::PathError,
+    ipld::Ipld,
+    multihash::Multihash,
+  };
+
+  fn example() -> Ipld {
+    let cid = Cid::new_v1(0x71, Multihash::sha3_256(b"x"));
+    Ipld::to_object(vec![
+      (
+        "foo".into(),
+        Ipld::Array(vec![
+          Ipld::Integer(0),
+          Ipld::to_object(vec![("bar".into(), Ipld::String("baz".into()))]),
+          Ipld::Link(cid),
+        ]),
+      ),
+      ("n".into(), Ipld::Null),
+    ])
+  }
+
+  #[test]
+  fn get_path() {
+    let ipld = example();
+    assert_eq!(ipld.get_path(""), Ok(&ipld));
+    assert_eq!(ipld.get_path("/"), Ok(&ipld));
+    assert_eq!(ipld.get_path("foo/0"), Ok(&Ipld::Integer(0)));
+    assert_eq!(ipld.get_path("/foo/1/bar/"), Ok(&Ipld::String("baz".into())));
+    assert_eq!(ipld.get_path("n"), Ok(&Ipld::Null));
+    assert_eq!(ipld.links().len(), 1);
+  }
+
+  #[test]
+  fn get_path_errors() {
+    let ipld = example();
+    assert_eq!(
+      ipld.get_path("foo/1/qux"),
+      Err(PathError::MissingKey { key: "qux".into(), path: "foo/1".into() })
+    );
+    assert_eq!(
+      ipld.get_path("foo/3"),
+      Err(PathError::IndexOutOfRange { index: 3, len: 3, path: "foo".into() })
+    );
+    for segment in ["bar", "01", "-1", "+1", "1.0"] {
+      assert_eq!(
+        ipld.get_path(&format!("foo/{}", segment)),
+        Err(PathError::InvalidIndex {
+          segment: segment.into(),
+          path: "foo".into()
+        })
+      );
+    }
+    assert_eq!(
+      ipld.get_path("n/x"),
+      Err(PathError::NotTraversable {
+        segment: "x".into(),
+        kind: "null",
+        path: "n".into()
+      })
+    );
+    assert_eq!(
+      ipld.get_path("foo/2/bar"),
+      Err(PathError::Link {
+        cid: Cid::new_v1(0x71, Multihash::sha3_256(b"x")),
+        path: "foo/2".into()
+      })
+    );
+  }
+}
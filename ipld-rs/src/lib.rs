@@ -1,10 +1,72 @@
-#![allow(dead_code)]
+//! Rust implementation of Ipld.lean.
+//!
+//! The most commonly used types and functions are re-exported at the crate
+//! root and in [`prelude`]; the individual modules expose the lower level
+//! building blocks.
 
-mod cid;
-mod dag_cbor;
-mod error;
-mod ipld;
-mod multibase;
-mod multihash;
-mod serde;
-mod unsigned_varint;
+pub mod block;
+pub mod car;
+pub mod carv2;
+pub mod cid;
+pub mod codec;
+pub mod dag_cbor;
+pub mod dag_json;
+pub mod dag_pb;
+pub mod error;
+pub mod ipld;
+pub mod multibase;
+pub mod multihash;
+pub mod serde;
+pub mod store;
+#[cfg(test)]
+mod test_util;
+pub mod traversal;
+pub mod unsigned_varint;
+
+pub use crate::{
+  block::Block,
+  cid::Cid,
+  codec::{
+    Codec,
+    Registry,
+  },
+  dag_cbor::{
+    deserialize as from_dag_cbor,
+    serialize as to_dag_cbor,
+  },
+  dag_json::{
+    deserialize as from_dag_json,
+    serialize as to_dag_json,
+  },
+  error::Error,
+  ipld::Ipld,
+  multibase::Multibase,
+  multihash::Multihash,
+  serde::{
+    from_ipld,
+    to_ipld,
+    SerdeError,
+  },
+};
+
+/// Glob-importable set of the types and functions needed to build, encode
+/// and decode IPLD data.
+pub mod prelude {
+  pub use crate::{
+    block::Block,
+    cid::Cid,
+    codec::{
+      Codec,
+      Registry,
+    },
+    from_dag_cbor,
+    from_dag_json,
+    from_ipld,
+    ipld::Ipld,
+    multibase::Multibase,
+    multihash::Multihash,
+    to_dag_cbor,
+    to_dag_json,
+    to_ipld,
+  };
+}
@@ -1,14 +1,56 @@
-// use crate::multibase_impl;
+use std::borrow::Cow;
+
+use crate::error::MultibaseError;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Multibase {
   code: char,
-  alpha: String,
+  alpha: Cow<'static, str>,
   rfc4648: bool,
   pad: bool,
 }
 
 impl Multibase {
+  /// Builds a custom base. The alphabet must hold at least two distinct ASCII
+  /// digits, and for RFC4648 a power of two of them, at most 256. Padding is
+  /// only written by RFC4648 bases, so it cannot use '=' as a digit.
+  pub fn new(
+    code: char,
+    alpha: &str,
+    rfc4648: bool,
+    pad: bool,
+  ) -> Result<Self, MultibaseError> {
+    let invalid = |reason| Err(MultibaseError::InvalidAlphabet(reason));
+    if !alpha.is_ascii() {
+      return invalid("digits must be ASCII");
+    }
+    if alpha.len() < 2 {
+      return invalid("fewer than two digits");
+    }
+    if alpha.bytes().enumerate().any(|(i, c)| alpha[..i].contains(c as char)) {
+      return invalid("duplicate digit");
+    }
+    if rfc4648 && (!alpha.len().is_power_of_two() || alpha.len() > 256) {
+      return invalid("RFC4648 needs a power of two digits, at most 256");
+    }
+    if pad && (!rfc4648 || alpha.contains('=')) {
+      return invalid("padding needs an RFC4648 base without '='");
+    }
+    Ok(Self { code, alpha: Cow::Owned(alpha.to_string()), rfc4648, pad })
+  }
+
+  // Constructor for the predefined bases, whose alphabets are static
+  const fn new_static(
+    code: char,
+    alpha: &'static str,
+    rfc4648: bool,
+    pad: bool,
+  ) -> Self {
+    Self { code, alpha: Cow::Borrowed(alpha), rfc4648, pad }
+  }
+
+  pub fn code(&self) -> char { self.code }
+
   // Returns the first scalar value in the alpha string
   fn zero(&self) -> char { self.alpha.chars().next().unwrap() }
 
@@ -21,13 +63,13 @@ impl Multibase {
   // Returns the RFC4648 base's group size in bits
   fn group(&self) -> u64 {
     let x = self.log2_base();
-    if x % 8 == 0 {
+    if x.is_multiple_of(8) {
       x
     }
-    else if x % 4 == 0 {
+    else if x.is_multiple_of(4) {
       x * 2
     }
-    else if x % 2 == 0 {
+    else if x.is_multiple_of(2) {
       x * 4
     }
     else {
@@ -52,11 +94,11 @@ impl Multibase {
   }
 
   // Checks if a char is contained in the alpha string
-  fn valid_digit(&self, c: char) -> bool { self.read(c) != None }
+  fn valid_digit(&self, c: char) -> bool { self.read(c).is_some() }
 
   // Checks if all characters in a given string are contained in the alpha
   // string
-  fn validate(&self, input: &str) -> bool {
+  pub fn validate(&self, input: &str) -> bool {
     for c in input.chars() {
       if !self.valid_digit(c) {
         return false;
@@ -65,175 +107,857 @@ impl Multibase {
     true
   }
 
+  // Returns the number of '=' completing `len` digits to a whole group
+  fn pad_len(&self, len: usize) -> usize {
+    let chars = (self.group() / self.log2_base()) as usize;
+    (chars - len % chars) % chars
+  }
+
   // Appends a given number of '=' characters to a string
   fn pad_right(&self, input: &mut String, num_pad: usize) {
     let pad: String = "=".repeat(num_pad);
     input.push_str(&pad)
   }
 
-  // Returns the number of leading zero bits in an array
-  fn leading_zero_bits(&self, input: &[u8]) -> u64 {
-    let mut zeros = 0;
-    for byte in input {
-      zeros += byte.leading_zeros();
-      if *byte != 0 {
-        break;
-      }
-    }
-    zeros.into()
-  }
-
-  // TODO: handle error gracefully
   // Checks if first char of given string is the same as the Multibase code
   // If so, returns the rest of the string
-  fn read_code(&self, input: &str) -> Result<String, String> {
-    match input.chars().nth(0) {
-      Some(c) => {
-    	if c == self.code {
-    	  Ok(input.strip_prefix(c).unwrap().to_string())
-    	}
-    	else {
-    	  Err("Invalid multibase input".into())
-    	}
-      },
-      _ => Err("Empty string".into()),
+  fn read_code(&self, input: &str) -> Result<String, MultibaseError> {
+    match input.chars().next() {
+      Some(c) if c == self.code => Ok(input[c.len_utf8()..].to_string()),
+      Some(c) => Err(MultibaseError::Code { expected: self.code, found: c }),
+      None => Err(MultibaseError::Empty),
     }
   }
-  
-  // Returns the number of characters equal to alpha[0] in a given string
-  fn read_zeros(&self, input: &str) -> u64 {
+
+  // Returns the number of leading characters equal to alpha[0] in a given
+  // string
+  fn read_zeros(&self, input: &str) -> usize {
     let zero = self.zero();
-    let iter = input.chars();
-    iter.fold(0, |acc, x| if x == zero { acc + 1 } else { acc })
+    input.chars().take_while(|c| *c == zero).count()
   }
 
   // Converts a list of bytes into a base encoding
   pub fn encode(&self, input: &[u8]) -> String {
-    let mut data = input.to_vec();
-    let log = self.log2_base() as usize;
-    let lzs = self.leading_zero_bits(&data) as usize;
-    let rfc = self.rfc4648;
-    let zeros: String = match rfc {
-      true => self.zero().to_string().repeat(lzs / log),
-      _ => self.zero().to_string().repeat(lzs / 8),
-    };
-    let grp = self.group() as usize / 8;
-    let byte_pad = (grp - data.len() % grp) % grp;
-    if rfc {
-      data.extend(vec![0; byte_pad]);
-    }
-    // TODO: handle error gracefully
-    // Converts a big-endian list of bytes into u128, then into numeric string
-    // form Seems like a compression operation
-    let n = read_be_u128(&data).unwrap();
-    let mut encode_str = n.to_string();
-    let char_pad = (byte_pad * 8) / log;
-    if rfc {
-      // TODO: Should it be char_pad - 1 (if char_pad > 0)?
-      encode_str = encode_str.drain(..char_pad).collect();
-      if self.pad {
-        self.pad_right(&mut encode_str, char_pad);
-      }
+    if self.rfc4648 {
+      self.encode_rfc4648(input)
+    }
+    else {
+      self.encode_radix(input)
     }
-    self.code.to_string() + &zeros + &encode_str
   }
 
   // Converts base-encoded bytes into base
-  pub fn decode(&self, input: &str) -> Result<Vec<u8>, String> {
-    let mut data = self.read_code(input)?;
-    let mut len = data.len();
-    let mut zero_chars = self.read_zeros(&data) as usize;
-    let log = self.log2_base() as usize;
-    if self.rfc4648 {
-      let grp = self.group() as usize;
-      let chars = grp / log;
-      let in_bits = len * log;
-      let rem_bits = in_bits % grp;
-      let pad = ((grp - rem_bits) / log) % chars;
-      self.pad_right(&mut data, pad);
+  pub fn decode(&self, input: &str) -> Result<Vec<u8>, MultibaseError> {
+    let data = self.read_code(input)?;
+    // Padded bases must end with exactly the padding `encode` writes, any
+    // other '=' is an invalid digit
+    let digits = if self.pad { data.trim_end_matches('=') } else { &data[..] };
+    if self.pad {
+      let expected = self.pad_len(digits.chars().count());
+      let found = data.len() - digits.len();
+      if found != expected {
+        return Err(MultibaseError::Padding { expected, found });
+      }
     }
-    if data.is_empty() {
-      Ok(vec![])
+    if let Some((offset, digit)) =
+      digits.chars().enumerate().find(|(_, c)| !self.valid_digit(*c))
+    {
+      return Err(MultibaseError::Digit { digit, offset: offset + 1 });
+    }
+    if self.rfc4648 {
+      self.decode_rfc4648(digits)
     }
     else {
-      let data_num: u128 = data.parse().unwrap();
-      // Converts to sized 16 byte BE array
-      let out = data_num.to_be_bytes().to_vec();
-      let mut pad_len = 0;
-      // TODO: rewrite with iterators
-      for i in out.iter().rev() {
-        if *i == 0 {
-          pad_len += 1;
-        }
-        else {
-          break;
-        }
+      Ok(self.decode_radix(digits))
+    }
+  }
+
+  // RFC4648 bases have a power of two number of digits, so every digit maps
+  // to exactly `log2_base` bits of the input, most significant bits first.
+  // The final digit is filled with zero bits, and with explicit padding the
+  // output is completed to a whole group with '=' characters
+  fn encode_rfc4648(&self, input: &[u8]) -> String {
+    let log = self.log2_base() as u32;
+    let mask = (1u32 << log) - 1;
+    let mut out = self.code.to_string();
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for byte in input {
+      acc = (acc << 8) | u32::from(*byte);
+      bits += 8;
+      while bits >= log {
+        bits -= log;
+        out.push(self.digit(((acc >> bits) & mask) as usize));
+      }
+      acc &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+      out.push(self.digit(((acc << (log - bits)) & mask) as usize));
+    }
+    if self.pad {
+      let len = out.len() - self.code.len_utf8();
+      self.pad_right(&mut out, self.pad_len(len));
+    }
+    out
+  }
+
+  // Inverse of `encode_rfc4648`: expects the digits to be validated already
+  // and the padding removed. The leftover bits of the final digit must be
+  // zero and fewer than a whole digit, so that every byte string has exactly
+  // one encoding
+  fn decode_rfc4648(&self, input: &str) -> Result<Vec<u8>, MultibaseError> {
+    let log = self.log2_base() as u32;
+    let mut out = Vec::with_capacity(input.len() * log as usize / 8);
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for c in input.chars() {
+      acc = (acc << log) | self.read(c).unwrap() as u32;
+      bits += log;
+      if bits >= 8 {
+        bits -= 8;
+        out.push((acc >> bits) as u8);
       }
-      if !self.pad {
-        len += pad_len;
+      acc &= (1 << bits) - 1;
+    }
+    if bits >= log || acc != 0 {
+      let offset = input.chars().count();
+      return Err(MultibaseError::TrailingBits { offset });
+    }
+    Ok(out)
+  }
+
+  // Outside of RFC4648 the input is read as one big-endian number and written
+  // in the base, with every leading zero byte kept as one zero digit
+  fn encode_radix(&self, input: &[u8]) -> String {
+    let base = self.base() as u32;
+    let zeros = input.iter().take_while(|b| **b == 0).count();
+    // Little-endian digits of the number, built by repeated multiplication
+    let mut digits: Vec<u32> = vec![];
+    for byte in &input[zeros..] {
+      let mut carry = u32::from(*byte);
+      for digit in digits.iter_mut() {
+        carry += *digit << 8;
+        *digit = carry % base;
+        carry /= base;
       }
-      if self.rfc4648 {
-        zero_chars = zero_chars * log / 8;
-        if len * log > out.len() * 8 + zero_chars * log {
-          zero_chars += 1;
-        }
+      while carry > 0 {
+        digits.push(carry % base);
+        carry /= base;
       }
-      let mut pad_bytes = pad_len * log / 8;
-      if (pad_len * log) % 8 == 0 {
-        pad_bytes += 1;
+    }
+    let mut out = self.code.to_string();
+    out.extend(std::iter::repeat_n(self.zero(), zeros));
+    out.extend(digits.iter().rev().map(|d| self.digit(*d as usize)));
+    out
+  }
+
+  // Inverse of `encode_radix`, expects the digits to be validated already
+  fn decode_radix(&self, input: &str) -> Vec<u8> {
+    let base = self.base() as u32;
+    let zeros = self.read_zeros(input);
+    // Little-endian bytes of the number
+    let mut bytes: Vec<u8> = vec![];
+    for c in input.chars().skip(zeros) {
+      let mut carry = self.read(c).unwrap() as u32;
+      for byte in bytes.iter_mut() {
+        carry += u32::from(*byte) * base;
+        *byte = carry as u8;
+        carry >>= 8;
+      }
+      while carry > 0 {
+        bytes.push(carry as u8);
+        carry >>= 8;
       }
-      let mut zeros: Vec<u8> = vec![0; zero_chars];
-      zeros.extend(&out[..out.len() - pad_bytes]);
-      Ok(zeros)
     }
+    let mut out = vec![0; zeros];
+    out.extend(bytes.iter().rev());
+    out
   }
 }
 
-// Converts an unsized slice into a 16-element array, then reads it into
-// big-endian u128
-pub fn read_be_u128(input: &[u8]) -> Result<u128, String> {
-  if input.len() > 16 {
-    Err("Input too large".into())
+// The bases of the multibase table, as defined in `Ipld/MultibaseImpl.lean`
+pub static BASE2: Multibase = Multibase::new_static('0', "01", true, false);
+pub static BASE8: Multibase =
+  Multibase::new_static('7', "01234567", true, false);
+pub static BASE10: Multibase =
+  Multibase::new_static('9', "0123456789", false, false);
+pub static BASE16: Multibase =
+  Multibase::new_static('f', "0123456789abcdef", true, false);
+pub static BASE16_UPPER: Multibase =
+  Multibase::new_static('F', "0123456789ABCDEF", true, false);
+pub static BASE32_HEX: Multibase =
+  Multibase::new_static('v', "0123456789abcdefghijklmnopqrstuv", true, false);
+pub static BASE32_HEX_UPPER: Multibase =
+  Multibase::new_static('V', "0123456789ABCDEFGHIJKLMNOPQRSTUV", true, false);
+pub static BASE32_HEX_PAD: Multibase =
+  Multibase::new_static('t', "0123456789abcdefghijklmnopqrstuv", true, true);
+pub static BASE32_HEX_PAD_UPPER: Multibase =
+  Multibase::new_static('T', "0123456789ABCDEFGHIJKLMNOPQRSTUV", true, true);
+pub static BASE32: Multibase =
+  Multibase::new_static('b', "abcdefghijklmnopqrstuvwxyz234567", true, false);
+pub static BASE32_UPPER: Multibase =
+  Multibase::new_static('B', "ABCDEFGHIJKLMNOPQRSTUVWXYZ234567", true, false);
+pub static BASE32_PAD: Multibase =
+  Multibase::new_static('c', "abcdefghijklmnopqrstuvwxyz234567", true, true);
+pub static BASE32_PAD_UPPER: Multibase =
+  Multibase::new_static('C', "ABCDEFGHIJKLMNOPQRSTUVWXYZ234567", true, true);
+pub static BASE32_Z: Multibase =
+  Multibase::new_static('h', "ybndrfg8ejkmcpqxot1uwisza345h769", true, false);
+pub static BASE36: Multibase = Multibase::new_static(
+  'k',
+  "0123456789abcdefghijklmnopqrstuvwxyz",
+  false,
+  false,
+);
+pub static BASE36_UPPER: Multibase = Multibase::new_static(
+  'K',
+  "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+  false,
+  false,
+);
+pub static BASE58_FLICKR: Multibase = Multibase::new_static(
+  'Z',
+  "123456789abcdefghijkmnopqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ",
+  false,
+  false,
+);
+pub static BASE58_BTC: Multibase = Multibase::new_static(
+  'z',
+  "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz",
+  false,
+  false,
+);
+pub static BASE64: Multibase = Multibase::new_static(
+  'm',
+  "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/",
+  true,
+  false,
+);
+pub static BASE64_PAD: Multibase = Multibase::new_static(
+  'M',
+  "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/",
+  true,
+  true,
+);
+pub static BASE64_URL: Multibase = Multibase::new_static(
+  'u',
+  "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_",
+  true,
+  false,
+);
+pub static BASE64_URL_PAD: Multibase = Multibase::new_static(
+  'U',
+  "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_",
+  true,
+  true,
+);
+
+/// A named multibase encoding, identified in strings by its code character.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Base {
+  Base2,
+  Base8,
+  Base10,
+  Base16,
+  Base16Upper,
+  Base32Hex,
+  Base32HexUpper,
+  Base32HexPad,
+  Base32HexPadUpper,
+  Base32,
+  Base32Upper,
+  Base32Pad,
+  Base32PadUpper,
+  Base32Z,
+  Base36,
+  Base36Upper,
+  Base58Flickr,
+  Base58Btc,
+  Base64,
+  Base64Pad,
+  Base64Url,
+  Base64UrlPad,
+}
+
+impl Base {
+  pub const ALL: [Base; 22] = [
+    Base::Base2,
+    Base::Base8,
+    Base::Base10,
+    Base::Base16,
+    Base::Base16Upper,
+    Base::Base32Hex,
+    Base::Base32HexUpper,
+    Base::Base32HexPad,
+    Base::Base32HexPadUpper,
+    Base::Base32,
+    Base::Base32Upper,
+    Base::Base32Pad,
+    Base::Base32PadUpper,
+    Base::Base32Z,
+    Base::Base36,
+    Base::Base36Upper,
+    Base::Base58Flickr,
+    Base::Base58Btc,
+    Base::Base64,
+    Base::Base64Pad,
+    Base::Base64Url,
+    Base::Base64UrlPad,
+  ];
+
+  /// Returns the base whose multibase code is `code`.
+  pub fn from_code(code: char) -> Option<Base> {
+    Base::ALL.into_iter().find(|base| base.code() == code)
   }
-  else {
-    let mut res = [0u8; 16];
-    let mut idx = res.len() - 1;
-    for i in input {
-      res[idx] = *i;
-      idx -= 1;
+
+  pub fn code(&self) -> char { self.multibase().code }
+
+  pub fn multibase(&self) -> &'static Multibase {
+    match self {
+      Base::Base2 => &BASE2,
+      Base::Base8 => &BASE8,
+      Base::Base10 => &BASE10,
+      Base::Base16 => &BASE16,
+      Base::Base16Upper => &BASE16_UPPER,
+      Base::Base32Hex => &BASE32_HEX,
+      Base::Base32HexUpper => &BASE32_HEX_UPPER,
+      Base::Base32HexPad => &BASE32_HEX_PAD,
+      Base::Base32HexPadUpper => &BASE32_HEX_PAD_UPPER,
+      Base::Base32 => &BASE32,
+      Base::Base32Upper => &BASE32_UPPER,
+      Base::Base32Pad => &BASE32_PAD,
+      Base::Base32PadUpper => &BASE32_PAD_UPPER,
+      Base::Base32Z => &BASE32_Z,
+      Base::Base36 => &BASE36,
+      Base::Base36Upper => &BASE36_UPPER,
+      Base::Base58Flickr => &BASE58_FLICKR,
+      Base::Base58Btc => &BASE58_BTC,
+      Base::Base64 => &BASE64,
+      Base::Base64Pad => &BASE64_PAD,
+      Base::Base64Url => &BASE64_URL,
+      Base::Base64UrlPad => &BASE64_URL_PAD,
     }
-    Ok(u128::from_be_bytes(res))
+  }
+
+  pub fn encode(&self, input: &[u8]) -> String {
+    self.multibase().encode(input)
+  }
+
+  /// Base16, base32 (except z-base-32) and base36 digits may be written in
+  /// either case, only the code character tells the upper and lower case
+  /// variants apart.
+  pub fn case_insensitive(&self) -> bool {
+    !matches!(
+      self,
+      Base::Base2
+        | Base::Base8
+        | Base::Base10
+        | Base::Base32Z
+        | Base::Base58Flickr
+        | Base::Base58Btc
+        | Base::Base64
+        | Base::Base64Pad
+        | Base::Base64Url
+        | Base::Base64UrlPad
+    )
+  }
+
+  pub fn decode(&self, input: &str) -> Result<Vec<u8>, MultibaseError> {
+    if !self.case_insensitive() {
+      return self.multibase().decode(input);
+    }
+    let code = input.chars().next().ok_or(MultibaseError::Empty)?;
+    let digits = &input[code.len_utf8()..];
+    // The alphabet has the same case as the code character
+    let folded = if code.is_ascii_lowercase() {
+      digits.to_ascii_lowercase()
+    }
+    else {
+      digits.to_ascii_uppercase()
+    };
+    self.multibase().decode(&format!("{}{}", code, folded)).map_err(|e| match e
+    {
+      // Report the digit as it was written
+      MultibaseError::Digit { offset, .. } => MultibaseError::Digit {
+        digit: input.chars().nth(offset).unwrap(),
+        offset,
+      },
+      e => e,
+    })
   }
 }
 
-// pub fn encode_bytes(input: Vec<u8>) -> String {
-//  Multibase::encode(input)
-//}
-// pub fn decode_bytes(x: String) -> Result<Vec<u8>, String> {
-//  Multibase::decode(x)
-//}
+/// Decodes a multibase string in whichever base its code character names.
+pub fn decode(input: &str) -> Result<(Base, Vec<u8>), MultibaseError> {
+  let code = input.chars().next().ok_or(MultibaseError::Empty)?;
+  let base = Base::from_code(code).ok_or(MultibaseError::UnknownCode(code))?;
+  Ok((base, base.decode(input)?))
+}
 
 #[cfg(test)]
 mod tests {
-  use crate::multibase::Multibase;
+  use crate::{
+    error::MultibaseError,
+    multibase::{
+      decode,
+      Base,
+      Multibase,
+    },
+  };
 
-  #[ignore]
   #[test]
   fn multibase_roundtrip() {
     let data = b"Hello, world!".to_vec();
-    let base2 = Multibase {
-      code: '0',
-      alpha: String::from("01"),
-      rfc4648: true,
-      pad: false,
-    };
+    let base2 =
+      Multibase { code: '0', alpha: "01".into(), rfc4648: true, pad: false };
     let base32 = Multibase {
       code: 'b',
-      alpha: String::from("abcdefghijklmnopqrstuvwxyz234567"),
+      alpha: "abcdefghijklmnopqrstuvwxyz234567".into(),
       rfc4648: true,
       pad: false,
     };
     assert_eq!(data, base2.decode(&base2.encode(&data)).unwrap());
     assert_eq!(data, base32.decode(&base32.encode(&data)).unwrap());
   }
+
+  #[test]
+  fn rfc4648_vectors() {
+    // RFC4648 section 10 test vectors, the prefixes of "foobar"
+    let cases = [
+      (Base::Base16, [
+        "f",
+        "f66",
+        "f666f",
+        "f666f6f",
+        "f666f6f62",
+        "f666f6f6261",
+        "f666f6f626172",
+      ]),
+      (Base::Base16Upper, [
+        "F",
+        "F66",
+        "F666F",
+        "F666F6F",
+        "F666F6F62",
+        "F666F6F6261",
+        "F666F6F626172",
+      ]),
+      (Base::Base32Hex, [
+        "v",
+        "vco",
+        "vcpng",
+        "vcpnmu",
+        "vcpnmuog",
+        "vcpnmuoj1",
+        "vcpnmuoj1e8",
+      ]),
+      (Base::Base32HexUpper, [
+        "V",
+        "VCO",
+        "VCPNG",
+        "VCPNMU",
+        "VCPNMUOG",
+        "VCPNMUOJ1",
+        "VCPNMUOJ1E8",
+      ]),
+      (Base::Base32HexPad, [
+        "t",
+        "tco======",
+        "tcpng====",
+        "tcpnmu===",
+        "tcpnmuog=",
+        "tcpnmuoj1",
+        "tcpnmuoj1e8======",
+      ]),
+      (Base::Base32HexPadUpper, [
+        "T",
+        "TCO======",
+        "TCPNG====",
+        "TCPNMU===",
+        "TCPNMUOG=",
+        "TCPNMUOJ1",
+        "TCPNMUOJ1E8======",
+      ]),
+      (Base::Base32, [
+        "b",
+        "bmy",
+        "bmzxq",
+        "bmzxw6",
+        "bmzxw6yq",
+        "bmzxw6ytb",
+        "bmzxw6ytboi",
+      ]),
+      (Base::Base32Upper, [
+        "B",
+        "BMY",
+        "BMZXQ",
+        "BMZXW6",
+        "BMZXW6YQ",
+        "BMZXW6YTB",
+        "BMZXW6YTBOI",
+      ]),
+      (Base::Base32Pad, [
+        "c",
+        "cmy======",
+        "cmzxq====",
+        "cmzxw6===",
+        "cmzxw6yq=",
+        "cmzxw6ytb",
+        "cmzxw6ytboi======",
+      ]),
+      (Base::Base32PadUpper, [
+        "C",
+        "CMY======",
+        "CMZXQ====",
+        "CMZXW6===",
+        "CMZXW6YQ=",
+        "CMZXW6YTB",
+        "CMZXW6YTBOI======",
+      ]),
+      (Base::Base64, [
+        "m",
+        "mZg",
+        "mZm8",
+        "mZm9v",
+        "mZm9vYg",
+        "mZm9vYmE",
+        "mZm9vYmFy",
+      ]),
+      (Base::Base64Pad, [
+        "M",
+        "MZg==",
+        "MZm8=",
+        "MZm9v",
+        "MZm9vYg==",
+        "MZm9vYmE=",
+        "MZm9vYmFy",
+      ]),
+      (Base::Base64UrlPad, [
+        "U",
+        "UZg==",
+        "UZm8=",
+        "UZm9v",
+        "UZm9vYg==",
+        "UZm9vYmE=",
+        "UZm9vYmFy",
+      ]),
+    ];
+    for (base, strings) in cases {
+      for (len, string) in strings.into_iter().enumerate() {
+        let data = &b"foobar"[..len];
+        assert_eq!(base.encode(data), string);
+        assert_eq!(base.decode(string).unwrap(), data);
+      }
+    }
+  }
+
+  #[test]
+  fn multibase_malformed() {
+    let base2 = Multibase::new('0', "01", true, false).unwrap();
+    assert_eq!(base2.decode(""), Err(MultibaseError::Empty));
+    assert_eq!(
+      base2.decode("b0101"),
+      Err(MultibaseError::Code { expected: '0', found: 'b' })
+    );
+    assert_eq!(
+      base2.decode("0012"),
+      Err(MultibaseError::Digit { digit: '2', offset: 3 })
+    );
+    // "f" is `bmy` and `mZg`, other final digits would leave non-zero bits
+    assert_eq!(Base::Base32.decode("bmy").unwrap(), b"f");
+    assert_eq!(
+      Base::Base32.decode("bmz"),
+      Err(MultibaseError::TrailingBits { offset: 2 })
+    );
+    assert_eq!(
+      Base::Base64.decode("mZh"),
+      Err(MultibaseError::TrailingBits { offset: 2 })
+    );
+    assert_eq!(
+      Base::Base64Pad.decode("MZh=="),
+      Err(MultibaseError::TrailingBits { offset: 2 })
+    );
+    // A final digit that does not complete a byte
+    assert_eq!(
+      Base::Base32.decode("bmya"),
+      Err(MultibaseError::TrailingBits { offset: 3 })
+    );
+    assert_eq!(
+      Base::Base16.decode("f6"),
+      Err(MultibaseError::TrailingBits { offset: 1 })
+    );
+    // Unpadded bases take no '=', padded ones exactly the padding written
+    assert_eq!(
+      Base::Base32.decode("bmy="),
+      Err(MultibaseError::Digit { digit: '=', offset: 3 })
+    );
+    assert_eq!(
+      Base::Base32.decode("bmy==="),
+      Err(MultibaseError::Digit { digit: '=', offset: 3 })
+    );
+    assert_eq!(Base::Base64Pad.decode("MAQ==").unwrap(), [1]);
+    assert_eq!(
+      Base::Base64Pad.decode("MAQ========"),
+      Err(MultibaseError::Padding { expected: 2, found: 8 })
+    );
+    assert_eq!(
+      Base::Base64Pad.decode("MAQ"),
+      Err(MultibaseError::Padding { expected: 2, found: 0 })
+    );
+    assert_eq!(
+      Base::Base32Pad.decode("cmy======="),
+      Err(MultibaseError::Padding { expected: 6, found: 7 })
+    );
+    assert_eq!(
+      Base::Base64Pad.decode("MAQID===="),
+      Err(MultibaseError::Padding { expected: 0, found: 4 })
+    );
+  }
+
+  #[test]
+  fn custom_alphabets() {
+    let err =
+      |alpha, rfc4648, pad| match Multibase::new('x', alpha, rfc4648, pad) {
+        Err(MultibaseError::InvalidAlphabet(reason)) => reason,
+        other => panic!("{:?}", other),
+      };
+    assert_eq!(err("", false, false), "fewer than two digits");
+    assert_eq!(err("0", true, false), "fewer than two digits");
+    assert_eq!(err("0é", false, false), "digits must be ASCII");
+    assert_eq!(err("0120", false, false), "duplicate digit");
+    assert_eq!(
+      err("012", true, false),
+      "RFC4648 needs a power of two digits, at most 256"
+    );
+    assert_eq!(
+      err("0123", false, true),
+      "padding needs an RFC4648 base without '='"
+    );
+    assert_eq!(
+      err("0=", true, true),
+      "padding needs an RFC4648 base without '='"
+    );
+    // A valid custom base behaves like the built-in one with its alphabet
+    let base4 = Multibase::new('x', "acgt", true, false).unwrap();
+    assert_eq!(base4.encode(b"\x1b"), "xacgt");
+    assert_eq!(base4.decode("xacgt").unwrap(), b"\x1b");
+    let base3 = Multibase::new('x', "012", false, false).unwrap();
+    assert_eq!(base3.decode(&base3.encode(b"\0ipld")).unwrap(), b"\0ipld");
+  }
+
+  #[test]
+  fn radix_vectors() {
+    // From the Lean test suite
+    let basic = b"yes mani !";
+    let vectors = [
+      (Base::Base36, "k2lcpzo5yikidynfl"),
+      (Base::Base58Btc, "z7paNL19xttacUY"),
+      (Base::Base32, "bpfsxgidnmfxgsibb"),
+      (Base::Base64, "meWVzIG1hbmkgIQ"),
+    ];
+    for (base, string) in vectors {
+      assert_eq!(base.encode(basic), string);
+      assert_eq!(base.decode(string).unwrap(), basic);
+      assert_eq!(Base::from_code(base.code()), Some(base));
+    }
+    assert_eq!(Base::Base58Btc.encode(b"\0\0yes mani !"), "z117paNL19xttacUY");
+    assert_eq!(
+      Base::Base58Btc.decode("z117paNL19xttacUY").unwrap(),
+      b"\0\0yes mani !"
+    );
+    assert_eq!(Base::Base58Btc.encode(&[]), "z");
+    assert_eq!(Base::Base58Btc.decode("z").unwrap(), b"");
+    assert_eq!(
+      Base::Base58Btc.decode("z0"),
+      Err(MultibaseError::Digit { digit: '0', offset: 1 })
+    );
+    assert_eq!(Base::from_code('?'), None);
+  }
+
+  #[test]
+  fn spec_vectors() {
+    // From the Lean test suite: "yes mani !" behind zero, one and two zero
+    // bytes. RFC4648 bases write zero bits as digits, others one digit a byte
+    let cases = [
+      (Base::Base2, [
+        "001111001011001010111001100100000011011010110000101101110011010010010000000100001",
+        "00000000001111001011001010111001100100000011011010110000101101110011010010010000000100001",
+        "0000000000000000001111001011001010111001100100000011011010110000101101110011010010010000000100001",
+      ]),
+      (Base::Base8, [
+        "7362625631006654133464440102",
+        "7000745453462015530267151100204",
+        "700000171312714403326055632220041",
+      ]),
+      (Base::Base10, [
+        "9573277761329450583662625",
+        "90573277761329450583662625",
+        "900573277761329450583662625",
+      ]),
+      (Base::Base16, [
+        "f796573206d616e692021",
+        "f00796573206d616e692021",
+        "f0000796573206d616e692021",
+      ]),
+      (Base::Base16Upper, [
+        "F796573206D616E692021",
+        "F00796573206D616E692021",
+        "F0000796573206D616E692021",
+      ]),
+      (Base::Base32Hex, [
+        "vf5in683dc5n6i811",
+        "v01smasp0dlgmsq9044",
+        "v0007ipbj41mm2rj940gg",
+      ]),
+      (Base::Base32HexUpper, [
+        "VF5IN683DC5N6I811",
+        "V01SMASP0DLGMSQ9044",
+        "V0007IPBJ41MM2RJ940GG",
+      ]),
+      (Base::Base32HexPad, [
+        "tf5in683dc5n6i811",
+        "t01smasp0dlgmsq9044======",
+        "t0007ipbj41mm2rj940gg====",
+      ]),
+      (Base::Base32HexPadUpper, [
+        "TF5IN683DC5N6I811",
+        "T01SMASP0DLGMSQ9044======",
+        "T0007IPBJ41MM2RJ940GG====",
+      ]),
+      (Base::Base32, [
+        "bpfsxgidnmfxgsibb",
+        "bab4wk4zanvqw42jaee",
+        "baaahszltebwwc3tjeaqq",
+      ]),
+      (Base::Base32Upper, [
+        "BPFSXGIDNMFXGSIBB",
+        "BAB4WK4ZANVQW42JAEE",
+        "BAAAHSZLTEBWWC3TJEAQQ",
+      ]),
+      (Base::Base32Pad, [
+        "cpfsxgidnmfxgsibb",
+        "cab4wk4zanvqw42jaee======",
+        "caaahszltebwwc3tjeaqq====",
+      ]),
+      (Base::Base32PadUpper, [
+        "CPFSXGIDNMFXGSIBB",
+        "CAB4WK4ZANVQW42JAEE======",
+        "CAAAHSZLTEBWWC3TJEAQQ====",
+      ]),
+      (Base::Base32Z, [
+        "hxf1zgedpcfzg1ebb",
+        "hybhskh3ypiosh4jyrr",
+        "hyyy813murbssn5ujryoo",
+      ]),
+      (Base::Base36, [
+        "k2lcpzo5yikidynfl",
+        "k02lcpzo5yikidynfl",
+        "k002lcpzo5yikidynfl",
+      ]),
+      (Base::Base36Upper, [
+        "K2LCPZO5YIKIDYNFL",
+        "K02LCPZO5YIKIDYNFL",
+        "K002LCPZO5YIKIDYNFL",
+      ]),
+      (Base::Base58Flickr, [
+        "Z7Pznk19XTTzBtx",
+        "Z17Pznk19XTTzBtx",
+        "Z117Pznk19XTTzBtx",
+      ]),
+      (Base::Base58Btc, [
+        "z7paNL19xttacUY",
+        "z17paNL19xttacUY",
+        "z117paNL19xttacUY",
+      ]),
+      (Base::Base64, [
+        "meWVzIG1hbmkgIQ",
+        "mAHllcyBtYW5pICE",
+        "mAAB5ZXMgbWFuaSAh",
+      ]),
+      (Base::Base64Pad, [
+        "MeWVzIG1hbmkgIQ==",
+        "MAHllcyBtYW5pICE=",
+        "MAAB5ZXMgbWFuaSAh",
+      ]),
+      (Base::Base64Url, [
+        "ueWVzIG1hbmkgIQ",
+        "uAHllcyBtYW5pICE",
+        "uAAB5ZXMgbWFuaSAh",
+      ]),
+      (Base::Base64UrlPad, [
+        "UeWVzIG1hbmkgIQ==",
+        "UAHllcyBtYW5pICE=",
+        "UAAB5ZXMgbWFuaSAh",
+      ]),
+    ];
+    assert_eq!(cases.len(), Base::ALL.len());
+    for (base, strings) in cases {
+      assert_eq!(Base::from_code(base.code()), Some(base));
+      for (zeros, string) in strings.into_iter().enumerate() {
+        let mut data = vec![0; zeros];
+        data.extend(b"yes mani !");
+        assert_eq!(base.encode(&data), string, "{:?}", base);
+        assert_eq!(base.decode(string).unwrap(), data, "{:?}", base);
+      }
+    }
+  }
+
+  #[test]
+  fn long_inputs() {
+    // Well past the 16 bytes the encoders used to be limited to
+    let data: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 256) as u8).collect();
+    for base in Base::ALL {
+      assert_eq!(base.decode(&base.encode(&data)).unwrap(), data);
+      assert_eq!(base.decode(&base.encode(&data[1..])).unwrap(), &data[1..]);
+    }
+  }
+
+  #[test]
+  fn decode_any_base() {
+    let data = b"yes mani !";
+    for base in Base::ALL {
+      assert_eq!(decode(&base.encode(data)), Ok((base, data.to_vec())));
+    }
+    assert_eq!(decode(""), Err(MultibaseError::Empty));
+    assert_eq!(decode("?abc"), Err(MultibaseError::UnknownCode('?')));
+    assert_eq!(
+      decode("z0"),
+      Err(MultibaseError::Digit { digit: '0', offset: 1 })
+    );
+  }
+
+  #[test]
+  fn case_insensitivity() {
+    // From the Lean test suite, "hello world" in mixed case
+    let cases = [
+      (Base::Base16, "f68656c6c6f20776F726C64"),
+      (Base::Base16Upper, "F68656c6c6f20776F726C64"),
+      (Base::Base32, "bnbswy3dpeB3W64TMMQ"),
+      (Base::Base32Upper, "Bnbswy3dpeB3W64TMMQ"),
+      (Base::Base32Hex, "vd1imor3f41RMUSJCCG"),
+      (Base::Base32HexUpper, "Vd1imor3f41RMUSJCCG"),
+      (Base::Base32Pad, "cnbswy3dpeB3W64TMMQ======"),
+      (Base::Base32PadUpper, "Cnbswy3dpeB3W64TMMQ======"),
+      (Base::Base32HexPad, "td1imor3f41RMUSJCCG======"),
+      (Base::Base32HexPadUpper, "Td1imor3f41RMUSJCCG======"),
+      (Base::Base36, "kfUvrsIvVnfRbjWaJo"),
+      (Base::Base36Upper, "KfUVrSIVVnFRbJWAJo"),
+    ];
+    for (base, string) in cases {
+      assert_eq!(decode(string), Ok((base, b"hello world".to_vec())));
+    }
+    // Case still matters where the alphabet uses both
+    assert_ne!(
+      Base::Base58Btc.decode("zStV1DL6CwTryKyV"),
+      Base::Base58Btc.decode("zstv1dl6cwtrykyv")
+    );
+    assert_eq!(
+      decode("bnbW1"),
+      Err(MultibaseError::Digit { digit: '1', offset: 4 })
+    );
+    assert_eq!(
+      decode("vnbW"),
+      Err(MultibaseError::Digit { digit: 'W', offset: 3 })
+    );
+  }
 }
@@ -1,19 +1,119 @@
-use crate::unsigned_varint::{
-  to_varint,
-  varint_read_u64,
+use crate::{
+  error::MultihashError,
+  unsigned_varint::{
+    to_varint,
+    varint_read_u64,
+  },
 };
 
+use blake2::{
+  digest::consts::U32,
+  Blake2b,
+  Blake2b512,
+};
+use sha2::{
+  Sha256,
+  Sha512,
+};
 use sha3::{
   Digest,
+  Keccak224,
+  Keccak256,
+  Keccak384,
+  Keccak512,
   Sha3_256,
   Sha3_512,
 };
 use std::{
   fmt,
-  io::Read,
+  io::{
+    self,
+    Read,
+    Write,
+  },
 };
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// The hash functions the crate can compute, with their multicodec codes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Code {
+  Identity,
+  Sha2_256,
+  Sha2_512,
+  Sha3_256,
+  Sha3_512,
+  Keccak224,
+  Keccak256,
+  Keccak384,
+  Keccak512,
+  Blake2b256,
+  Blake2b512,
+  Blake3,
+}
+
+impl Code {
+  pub const ALL: [Code; 12] = [
+    Code::Identity,
+    Code::Sha2_256,
+    Code::Sha2_512,
+    Code::Sha3_256,
+    Code::Sha3_512,
+    Code::Keccak224,
+    Code::Keccak256,
+    Code::Keccak384,
+    Code::Keccak512,
+    Code::Blake2b256,
+    Code::Blake2b512,
+    Code::Blake3,
+  ];
+
+  /// The multicodec code of the hash function.
+  pub fn code(&self) -> u64 {
+    match self {
+      Code::Identity => 0x00,
+      Code::Sha2_256 => 0x12,
+      Code::Sha2_512 => 0x13,
+      Code::Sha3_256 => 0x16,
+      Code::Sha3_512 => 0x14,
+      Code::Keccak224 => 0x1a,
+      Code::Keccak256 => 0x1b,
+      Code::Keccak384 => 0x1c,
+      Code::Keccak512 => 0x1d,
+      Code::Blake2b256 => 0xb220,
+      Code::Blake2b512 => 0xb240,
+      Code::Blake3 => 0x1e,
+    }
+  }
+
+  pub fn from_code(code: u64) -> Option<Code> {
+    Code::ALL.into_iter().find(|c| c.code() == code)
+  }
+
+  /// The length in bytes of a full digest, or `None` for the identity
+  /// "hash", whose digest is the input itself.
+  pub fn size(&self) -> Option<u64> {
+    match self {
+      Code::Identity => None,
+      Code::Keccak224 => Some(28),
+      Code::Keccak384 => Some(48),
+      Code::Sha2_512 | Code::Sha3_512 | Code::Keccak512 | Code::Blake2b512 => {
+        Some(64)
+      }
+      _ => Some(32),
+    }
+  }
+
+  /// The longest digest a multihash may declare, or `None` when any length is
+  /// valid: the identity "hash", and BLAKE3, whose output can be extended
+  /// past its default 32 bytes.
+  pub fn max_size(&self) -> Option<u64> {
+    match self {
+      Code::Blake3 => None,
+      _ => self.size(),
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct Multihash {
   code: u64,
   size: u64,
@@ -21,35 +121,170 @@ pub struct Multihash {
 }
 
 impl Multihash {
+  /// The multicodec code of the hash function.
+  pub fn code(&self) -> u64 { self.code }
+
+  /// The length of the digest in bytes.
+  pub fn size(&self) -> u64 { self.size }
+
+  /// The raw digest bytes.
+  pub fn digest(&self) -> &[u8] { &self.digest }
+
+  /// Hashes `bytes` with the hash function `code`.
+  pub fn hash(code: Code, bytes: &[u8]) -> Multihash {
+    let mut hasher = MultihashHasher::new(code);
+    hasher.update(bytes);
+    hasher.finalize()
+  }
+
   pub fn to_bytes(&self) -> Vec<u8> {
-    let mut code = to_varint(self.code);
-    code.extend(to_varint(self.size));
+    // Codes come from `Code` or were decoded, and sizes are digest lengths,
+    // so both always fit in a varint
+    let mut code = to_varint(self.code).unwrap();
+    code.extend(to_varint(self.size).unwrap());
     code.extend(self.digest.clone());
     code
   }
 
-  pub fn from_bytes<R: Read>(r: &mut R) -> Result<Multihash, ()> {
-    let code = varint_read_u64(r).unwrap();
-    let size = varint_read_u64(r).unwrap();
+  pub fn from_bytes<R: Read>(r: &mut R) -> Result<Multihash, MultihashError> {
+    let code = varint_read_u64(r).map_err(MultihashError::Code)?;
+    let size = varint_read_u64(r).map_err(MultihashError::Size)?;
+    // Digests may be truncated, but never longer than the hash output
+    if let Some(max) = Code::from_code(code).and_then(|c| c.max_size()) {
+      if size > max {
+        return Err(MultihashError::InvalidSize { code, size });
+      }
+    }
 
-    let mut digest = vec![0; size as usize];
-    r.read_exact(&mut digest[..size as usize]).unwrap();
+    // Read through `take` so that a bogus size on truncated input cannot
+    // trigger a huge up-front allocation.
+    let mut digest = vec![];
+    r.take(size)
+      .read_to_end(&mut digest)
+      .map_err(|e| MultihashError::Io(e.kind()))?;
+    if digest.len() as u64 != size {
+      return Err(MultihashError::Truncated {
+        expected: size,
+        found: digest.len() as u64,
+      });
+    }
     Ok(Multihash { code, size, digest })
   }
 
-  pub fn sha3_256(bytes: &Vec<u8>) -> Multihash {
-    let mut hasher = Sha3_256::new();
-    hasher.update(bytes);
-    let digest = hasher.finalize().to_vec();
-    Multihash { code: 0x16, size: 32, digest }
+  pub fn sha3_256(bytes: &[u8]) -> Multihash {
+    Multihash::hash(Code::Sha3_256, bytes)
   }
 
-  pub fn sha3_512(bytes: &Vec<u8>) -> Multihash {
-    let mut hasher = Sha3_512::new();
-    hasher.update(bytes);
-    let digest = hasher.finalize().to_vec();
-    Multihash { code: 0x14, size: 64, digest }
+  pub fn sha3_512(bytes: &[u8]) -> Multihash {
+    Multihash::hash(Code::Sha3_512, bytes)
+  }
+
+  /// The original Keccak submission, which differs from SHA-3 only in its
+  /// padding. Matches `Keccak.keccak224` in `Ipld/Keccak.lean`.
+  pub fn keccak_224(bytes: &[u8]) -> Multihash {
+    Multihash::hash(Code::Keccak224, bytes)
+  }
+
+  pub fn keccak_256(bytes: &[u8]) -> Multihash {
+    Multihash::hash(Code::Keccak256, bytes)
+  }
+
+  pub fn keccak_384(bytes: &[u8]) -> Multihash {
+    Multihash::hash(Code::Keccak384, bytes)
+  }
+
+  pub fn keccak_512(bytes: &[u8]) -> Multihash {
+    Multihash::hash(Code::Keccak512, bytes)
+  }
+}
+
+/// Computes a multihash incrementally, from data written to it in pieces.
+pub struct MultihashHasher {
+  code: Code,
+  state: HasherState,
+}
+
+enum HasherState {
+  Identity(Vec<u8>),
+  Sha2_256(Sha256),
+  Sha2_512(Sha512),
+  Sha3_256(Sha3_256),
+  Sha3_512(Sha3_512),
+  Keccak224(Keccak224),
+  Keccak256(Keccak256),
+  Keccak384(Keccak384),
+  Keccak512(Keccak512),
+  Blake2b256(Blake2b<U32>),
+  Blake2b512(Blake2b512),
+  Blake3(Box<blake3::Hasher>),
+}
+
+impl MultihashHasher {
+  pub fn new(code: Code) -> Self {
+    let state = match code {
+      Code::Identity => HasherState::Identity(vec![]),
+      Code::Sha2_256 => HasherState::Sha2_256(Sha256::new()),
+      Code::Sha2_512 => HasherState::Sha2_512(Sha512::new()),
+      Code::Sha3_256 => HasherState::Sha3_256(Sha3_256::new()),
+      Code::Sha3_512 => HasherState::Sha3_512(Sha3_512::new()),
+      Code::Keccak224 => HasherState::Keccak224(Keccak224::new()),
+      Code::Keccak256 => HasherState::Keccak256(Keccak256::new()),
+      Code::Keccak384 => HasherState::Keccak384(Keccak384::new()),
+      Code::Keccak512 => HasherState::Keccak512(Keccak512::new()),
+      Code::Blake2b256 => HasherState::Blake2b256(Blake2b::new()),
+      Code::Blake2b512 => HasherState::Blake2b512(Blake2b512::new()),
+      Code::Blake3 => HasherState::Blake3(Box::default()),
+    };
+    MultihashHasher { code, state }
+  }
+
+  pub fn code(&self) -> Code { self.code }
+
+  pub fn update(&mut self, bytes: &[u8]) {
+    match &mut self.state {
+      HasherState::Identity(buf) => buf.extend_from_slice(bytes),
+      HasherState::Sha2_256(h) => h.update(bytes),
+      HasherState::Sha2_512(h) => h.update(bytes),
+      HasherState::Sha3_256(h) => h.update(bytes),
+      HasherState::Sha3_512(h) => h.update(bytes),
+      HasherState::Keccak224(h) => h.update(bytes),
+      HasherState::Keccak256(h) => h.update(bytes),
+      HasherState::Keccak384(h) => h.update(bytes),
+      HasherState::Keccak512(h) => h.update(bytes),
+      HasherState::Blake2b256(h) => h.update(bytes),
+      HasherState::Blake2b512(h) => h.update(bytes),
+      HasherState::Blake3(h) => {
+        h.update(bytes);
+      }
+    }
   }
+
+  pub fn finalize(self) -> Multihash {
+    let digest = match self.state {
+      HasherState::Identity(buf) => buf,
+      HasherState::Sha2_256(h) => h.finalize().to_vec(),
+      HasherState::Sha2_512(h) => h.finalize().to_vec(),
+      HasherState::Sha3_256(h) => h.finalize().to_vec(),
+      HasherState::Sha3_512(h) => h.finalize().to_vec(),
+      HasherState::Keccak224(h) => h.finalize().to_vec(),
+      HasherState::Keccak256(h) => h.finalize().to_vec(),
+      HasherState::Keccak384(h) => h.finalize().to_vec(),
+      HasherState::Keccak512(h) => h.finalize().to_vec(),
+      HasherState::Blake2b256(h) => h.finalize().to_vec(),
+      HasherState::Blake2b512(h) => h.finalize().to_vec(),
+      HasherState::Blake3(h) => h.finalize().as_bytes().to_vec(),
+    };
+    Multihash { code: self.code.code(), size: digest.len() as u64, digest }
+  }
+}
+
+impl Write for MultihashHasher {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.update(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> { Ok(()) }
 }
 
 impl fmt::Display for Multihash {
@@ -60,7 +295,25 @@ impl fmt::Display for Multihash {
 
 #[cfg(test)]
 mod tests {
-  use crate::multihash::Multihash;
+  use crate::{
+    dag_cbor,
+    error::{
+      MultihashError,
+      VarintError,
+    },
+    ipld::Ipld,
+    multibase::Base,
+    multihash::{
+      Code,
+      Multihash,
+      MultihashHasher,
+    },
+    test_util::hex,
+  };
+  use std::io::{
+    self,
+    Write,
+  };
 
   #[test]
   fn multihash_bytes_roundtrip() {
@@ -71,4 +324,163 @@ mod tests {
       Multihash::from_bytes(&mut &result.to_bytes()[..]).unwrap()
     );
   }
+
+  #[test]
+  fn multihash_malformed() {
+    let bytes = Multihash::sha3_256(&[1]).to_bytes();
+    assert_eq!(
+      Multihash::from_bytes(&mut &bytes[..0]),
+      Err(MultihashError::Code(VarintError::UnexpectedEof { offset: 0 }))
+    );
+    assert_eq!(
+      Multihash::from_bytes(&mut &bytes[..1]),
+      Err(MultihashError::Size(VarintError::UnexpectedEof { offset: 0 }))
+    );
+    assert_eq!(
+      Multihash::from_bytes(&mut &bytes[..10]),
+      Err(MultihashError::Truncated { expected: 32, found: 8 })
+    );
+    assert_eq!(
+      Multihash::from_bytes(&mut &[0x00, 0xff, 0xff, 0xff, 0xff, 0x0f][..]),
+      Err(MultihashError::Truncated { expected: 0xffffffff, found: 0 })
+    );
+    assert_eq!(
+      Multihash::from_bytes(&mut &[0x16, 0x21][..]),
+      Err(MultihashError::InvalidSize { code: 0x16, size: 0x21 })
+    );
+    // Truncated digests are allowed
+    let mut truncated = vec![0x12, 0x14];
+    truncated.extend([0; 20]);
+    assert_eq!(Multihash::from_bytes(&mut &truncated[..]).unwrap().size(), 20);
+    // So are extended BLAKE3 outputs
+    let mut digest = [0; 64];
+    blake3::Hasher::new().update(b"x").finalize_xof().fill(&mut digest);
+    let mut extended = vec![0x1e, 0x40];
+    extended.extend(digest);
+    let hash = Multihash::from_bytes(&mut &extended[..]).unwrap();
+    assert_eq!(
+      hash.digest()[..32],
+      Multihash::hash(Code::Blake3, b"x").digest()[..]
+    );
+    assert_eq!(hash.to_bytes(), extended);
+  }
+
+  #[test]
+  fn sha3_lean_vectors() {
+    // The cases of `Tests/Multihash.lean`, hashing the UTF-8 of each string
+    let cases = [
+      ("431fb5d4c9b735ba1a34d0df045118806ae2336f2c", "f14409a7a8207a57d03e9c524ae7fd39563bfe1a466a3a0323875eba8b034a1d59c3b7218103543f7777f17ef03dcaf44d12c74dfb83726e7425cf61225e9a54b3b3a"),
+      ("2d6db2d7882fa8b7d56e74b8e24036deb475de8c94", "f1440968e697b2d5e92470002f9e59e13557f47b895dc9c79082a90e91515f025563773aec0f70219c87350c79707de67500866d7fe084c5316e12c6930949b28865d"),
+      ("6c00022ba29d15926c4580332ded091e666f0ec5d9", "f144047f57e2056010afa03bc58141ba3754f41917518c81711236eaca3766e333b9a2a767a90363f7a179e776d85aa6610713709ee46531f9a454565f737c68bdc56"),
+      ("1391551dc8f15110d256c493ed485bca8cfed07241", "f144089c62811bc2d3fec81631112ad9f03de23d065697f758b8df9e5d791474ab2f20ddac684c23b203b730be465a5d0f06b76e9dbc48590def7d58e96d93b4e0418"),
+      ("557f47c855a5ca40daa3c0904a1e43647b4021ce0c", "f1440a7ab209784597d2e251860e2464c04c386c4887af778136414c80d7c643ccd3b2a0b61e31986a79ef8e4fdd41601fbe7c982c132df4bc77ecc2e27d3edd55f90"),
+      ("4c44254356730838195a32cbb1b8be3bed4c6c05c0", "f14403dc43b479f5e9e008a5256ca442e66286995c39deb732a6fb4e2e791a3c4a6a6e5322e571e945a78748896edc67866ab00c767320f6956833857eab991a73a77"),
+    ];
+    for (input, multihash) in cases {
+      let hash = Multihash::sha3_512(input.as_bytes());
+      // Strip the base16 multibase code
+      assert_eq!(hash.to_bytes(), hex(&multihash[1..]));
+    }
+  }
+
+  #[test]
+  fn keccak_vectors() {
+    // Published Keccak known answers for the empty string and "abc"
+    let cases = [
+      (Multihash::keccak_224(b""), 0x1a, "f71837502ba8e10837bdd8d365adb85591895602fc552b48b7390abd"),
+      (Multihash::keccak_256(b""), 0x1b, "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"),
+      (Multihash::keccak_256(b"abc"), 0x1b, "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45"),
+      (Multihash::keccak_384(b""), 0x1c, "2c23146a63a29acf99e73b88f8c24eaa7dc60aa771780ccc006afbfa8fe2479b2dd2b21362337441ac12b515911957ff"),
+      (Multihash::keccak_512(b""), 0x1d, "0eab42de4c3ceb9235fc91acffe746b29c29a8c366b7c60e4e67c466f36a4304c00fa9caf9d87976ba469bcbe06713b435f091ef2769fb160cdab33d3670680e"),
+      (Multihash::keccak_512(b"abc"), 0x1d, "18587dc2ea106b9a1563e32b3312421ca164c7f1f07bc922a9c83d77cea3a1e5d0c69910739025372dc14ac9642629379540c17e2a65b19d77aa511a9d00bb96"),
+    ];
+    for (hash, code, digest) in cases {
+      assert_eq!(hash.code(), code);
+      assert_eq!(hash.size(), digest.len() as u64 / 2);
+      assert_eq!(hash.digest(), hex(digest));
+      let bytes = hash.to_bytes();
+      assert_eq!(Multihash::from_bytes(&mut &bytes[..]).unwrap(), hash);
+    }
+  }
+
+  #[test]
+  fn keccak_shared_fixture() {
+    // Also checked by `Tests/Keccak.lean`, so that both implementations
+    // produce the same multihashes. Each line holds the hash function, the
+    // input and its multihash, both in base16 multibase
+    let fixture = include_str!("../tests/fixtures/keccak.txt");
+    for line in fixture.lines() {
+      let fields: Vec<&str> = line.split(' ').collect();
+      let [name, input, multihash] = fields[..]
+      else {
+        panic!("malformed fixture line `{}`", line)
+      };
+      let input = Base::Base16.decode(input).unwrap();
+      let hash = match name {
+        "keccak-224" => Multihash::keccak_224(&input),
+        "keccak-256" => Multihash::keccak_256(&input),
+        "keccak-384" => Multihash::keccak_384(&input),
+        "keccak-512" => Multihash::keccak_512(&input),
+        _ => panic!("unknown hash function `{}`", name),
+      };
+      assert_eq!(Base::Base16.encode(&hash.to_bytes()), multihash);
+    }
+  }
+
+  #[test]
+  fn digest_vectors() {
+    let cases = [
+      (Code::Sha2_256, &b""[..], "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+      (Code::Sha2_256, b"abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+      (Code::Sha2_512, b"abc", "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"),
+      (Code::Blake2b256, b"", "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8"),
+      (Code::Blake2b512, b"abc", "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"),
+      (Code::Blake3, b"", "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"),
+      (Code::Identity, b"abc", "616263"),
+    ];
+    for (code, input, digest) in cases {
+      let hash = Multihash::hash(code, input);
+      assert_eq!(hash.code(), code.code());
+      assert_eq!(hash.digest(), hex(digest));
+      let bytes = hash.to_bytes();
+      assert_eq!(Multihash::from_bytes(&mut &bytes[..]).unwrap(), hash);
+    }
+    for code in Code::ALL {
+      assert_eq!(Code::from_code(code.code()), Some(code));
+      let hash = Multihash::hash(code, b"x");
+      assert_eq!(Some(hash.size()), code.size().or(Some(1)));
+    }
+    assert_eq!(Code::from_code(0x99), None);
+  }
+
+  #[test]
+  fn incremental_hashing() {
+    let data: Vec<u8> = (0..10_000u32).map(|i| (i * 31 % 251) as u8).collect();
+    for code in Code::ALL {
+      let mut hasher = MultihashHasher::new(code);
+      for chunk in data.chunks(97) {
+        hasher.write_all(chunk).unwrap();
+      }
+      assert_eq!(hasher.finalize(), Multihash::hash(code, &data));
+
+      let mut hasher = MultihashHasher::new(code);
+      io::copy(&mut &data[..], &mut hasher).unwrap();
+      assert_eq!(hasher.code(), code);
+      assert_eq!(hasher.finalize(), Multihash::hash(code, &data));
+    }
+    assert_eq!(
+      MultihashHasher::new(Code::Sha2_256).finalize(),
+      Multihash::hash(Code::Sha2_256, b"")
+    );
+  }
+
+  #[test]
+  fn hash_while_encoding() {
+    let ipld =
+      Ipld::Array(vec![Ipld::String("hello".into()), Ipld::Integer(1)]);
+    let mut hasher = MultihashHasher::new(Code::Blake3);
+    dag_cbor::serialize_into(&ipld, &mut hasher).unwrap();
+    let bytes = dag_cbor::serialize(&ipld).unwrap();
+    assert_eq!(hasher.finalize(), Multihash::hash(Code::Blake3, &bytes));
+  }
 }
@@ -4,8 +4,8 @@ use crate::{
     Cid,
     CID_SERDE_PRIVATE_IDENTIFIER,
   },
+  error::SerdeError,
   ipld::Ipld,
-  serde::error::SerdeError,
 };
 
 use std::{
@@ -60,7 +60,25 @@ impl<'de> de::Deserialize<'de> for Ipld {
       #[inline]
       fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
       where E: de::Error {
-        Ok(Ipld::Number(value))
+        Ok(Ipld::Integer(value.into()))
+      }
+
+      #[inline]
+      fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
+      where E: de::Error {
+        Ok(Ipld::Integer(value.into()))
+      }
+
+      #[inline]
+      fn visit_i128<E>(self, value: i128) -> Result<Self::Value, E>
+      where E: de::Error {
+        Ok(Ipld::Integer(value))
+      }
+
+      #[inline]
+      fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E>
+      where E: de::Error {
+        Ok(Ipld::Float(value))
       }
 
       #[inline]
@@ -123,15 +141,15 @@ macro_rules! impl_deserialize_integer {
       visitor: V,
     ) -> Result<V::Value, Self::Error> {
       match self {
-        Self::Number(integer) => match $ty::try_from(integer) {
+        Self::Integer(integer) => match $ty::try_from(integer) {
           Ok(int) => visitor.$visit(int),
           Err(_) => error(format!(
-            "`Ipld::Number` value was bigger than `{}`",
+            "`Ipld::Integer` value was out of range for `{}`",
             stringify!($ty)
           )),
         },
         _ => error(format!(
-          "Only `Ipld::Number` can be deserialized to `{}`, input was `{:#?}`",
+          "Only `Ipld::Integer` can be deserialized to `{}`, input was `{:#?}`",
           stringify!($ty),
           self
         )),
@@ -166,7 +184,10 @@ impl<'de> de::Deserializer<'de> for CidDeserializer {
     self,
     visitor: V,
   ) -> Result<V::Value, Self::Error> {
-    visitor.visit_bytes(&self.0.to_bytes())
+    match self.0.to_bytes() {
+      Ok(bytes) => visitor.visit_bytes(&bytes),
+      Err(err) => error(err.to_string()),
+    }
   }
 }
 
@@ -174,7 +195,7 @@ impl<'de> de::Deserializer<'de> for CidDeserializer {
 ///
 /// The deserialization will return an error if you try to deserialize into an
 /// integer type that would be too small to hold the value stored in
-/// [`Ipld::Number`].
+/// [`Ipld::Integer`].
 impl<'de> de::Deserializer<'de> for Ipld {
   type Error = SerdeError;
 
@@ -186,6 +207,8 @@ impl<'de> de::Deserializer<'de> for Ipld {
 
   impl_deserialize_integer!(i64, deserialize_i64, visit_i64);
 
+  impl_deserialize_integer!(i128, deserialize_i128, visit_i128);
+
   impl_deserialize_integer!(u8, deserialize_u8, visit_u8);
 
   impl_deserialize_integer!(u16, deserialize_u16, visit_u16);
@@ -194,13 +217,26 @@ impl<'de> de::Deserializer<'de> for Ipld {
 
   impl_deserialize_integer!(u64, deserialize_u64, visit_u64);
 
+  impl_deserialize_integer!(u128, deserialize_u128, visit_u128);
+
   #[inline]
   fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
   where V: de::Visitor<'de> {
     match self {
       Self::Null => visitor.visit_none(),
       Self::Bool(b) => visitor.visit_bool(b),
-      Self::Number(n) => visitor.visit_u64(n),
+      Self::Integer(n) => {
+        if let Ok(n) = u64::try_from(n) {
+          visitor.visit_u64(n)
+        }
+        else if let Ok(n) = i64::try_from(n) {
+          visitor.visit_i64(n)
+        }
+        else {
+          visitor.visit_i128(n)
+        }
+      }
+      Self::Float(f) => visitor.visit_f64(f),
       Self::String(s) => visitor.visit_str(&s),
       Self::Bytes(b) => visitor.visit_bytes(&b),
       Self::Array(a) => visit_seq(a, visitor),
@@ -238,16 +274,28 @@ impl<'de> de::Deserializer<'de> for Ipld {
 
   fn deserialize_f32<V: de::Visitor<'de>>(
     self,
-    _visitor: V,
+    visitor: V,
   ) -> Result<V::Value, Self::Error> {
-    error("Floats not supported")
+    match self {
+      Self::Float(f) => visitor.visit_f32(f as f32),
+      _ => error(format!(
+        "Only `Ipld::Float` can be deserialized to f32, input was `{:#?}`",
+        self
+      )),
+    }
   }
 
   fn deserialize_f64<V: de::Visitor<'de>>(
     self,
-    _visitor: V,
+    visitor: V,
   ) -> Result<V::Value, Self::Error> {
-    error("Floats not supported")
+    match self {
+      Self::Float(f) => visitor.visit_f64(f),
+      _ => error(format!(
+        "Only `Ipld::Float` can be deserialized to f64, input was `{:#?}`",
+        self
+      )),
+    }
   }
 
   fn deserialize_char<V: de::Visitor<'de>>(
@@ -447,7 +495,7 @@ impl<'de> de::Deserializer<'de> for Ipld {
   ) -> Result<V::Value, Self::Error> {
     let (variant, value) = match self {
       Ipld::Array(xs) if !xs.is_empty() => match &xs[0] {
-        Ipld::Number(idx) if *idx < variants.len() as u64 => {
+        Ipld::Integer(idx) if (0..variants.len() as i128).contains(idx) => {
           let idx = *idx as usize;
           let variant = String::from(variants[idx]);
           let value = if xs.len() == 1 {
@@ -460,7 +508,7 @@ impl<'de> de::Deserializer<'de> for Ipld {
         }
         bad_tag => {
           return error(format!(
-            "`enum` tags must be an Ipld::Number between and the maximum \
+            "`enum` tags must be an Ipld::Integer between and the maximum \
              number of variants {:#?}, input was `{:#?}`",
             variants.len(),
             bad_tag.clone()
@@ -544,11 +592,15 @@ impl<'de> de::MapAccess<'de> for MapDeserializer {
           self.value = Some(val.to_owned());
           seed.deserialize(key.to_owned()).map(Some)
         }
-        _ => todo!("Error handling"),
+        _ => error(format!(
+          "Map entries must be `Ipld::Array`s of length 2, input was `{:#?}`",
+          xs
+        )),
       },
-      Some(_) => {
-        todo!("Error handling")
-      }
+      Some(entry) => error(format!(
+        "Map entries must be `Ipld::Array`s of length 2, input was `{:#?}`",
+        entry
+      )),
       None => Ok(None),
     }
   }
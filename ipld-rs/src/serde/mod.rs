@@ -1,28 +1,9 @@
 mod de;
 mod ser;
 
-// TODO: move to ../error.rs
-mod error {
-  #[derive(Clone, Debug)]
-  pub struct SerdeError(String);
-
-  impl core::fmt::Display for SerdeError {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-      write!(f, "Serde error: {}", self.0)
-    }
-  }
-
-  impl serde::de::Error for SerdeError {
-    fn custom<T: core::fmt::Display>(msg: T) -> Self { Self(msg.to_string()) }
-  }
-  impl serde::ser::Error for SerdeError {
-    fn custom<T: core::fmt::Display>(msg: T) -> Self { Self(msg.to_string()) }
-  }
-
-  impl serde::ser::StdError for SerdeError {}
-}
-
-// pub use error::SerdeError;
+pub use crate::error::SerdeError;
+pub use de::from_ipld;
+pub use ser::to_ipld;
 
 #[cfg(test)]
 mod tests {
@@ -44,7 +25,7 @@ mod tests {
   /// Checks if `data` and `ipld` match if they are encoded into each other.
   fn assert_roundtrip<T>(data: &T, ipld: &Ipld)
   where T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug {
-    let encoded: Ipld = to_ipld(&data).unwrap();
+    let encoded: Ipld = to_ipld(data).unwrap();
     assert_eq!(&encoded, ipld);
     let decoded: T = from_ipld(ipld.clone()).unwrap();
     assert_eq!(&decoded, data);
@@ -59,7 +40,31 @@ mod tests {
   #[test]
   fn ser_de_ipld() {
     let point = Point { x: 1, y: 2 };
-    let expected = Ipld::Array(vec![Ipld::Number(1), Ipld::Number(2)]);
+    let expected = Ipld::Array(vec![Ipld::Integer(1), Ipld::Integer(2)]);
     assert_roundtrip(&point, &expected);
   }
+
+  #[derive(Serialize, Deserialize, PartialEq, Debug)]
+  struct Reading {
+    offset: i32,
+    value: f64,
+  }
+
+  #[test]
+  fn ser_de_signed_and_float() {
+    let reading = Reading { offset: -7, value: 0.25 };
+    let expected = Ipld::Array(vec![Ipld::Integer(-7), Ipld::Float(0.25)]);
+    assert_roundtrip(&reading, &expected);
+    assert_roundtrip(&i64::MIN, &Ipld::Integer(i64::MIN.into()));
+    assert!(from_ipld::<u8>(Ipld::Integer(-1)).is_err());
+  }
+
+  #[test]
+  fn de_malformed_map() {
+    use std::collections::BTreeMap;
+    let ipld = Ipld::Array(vec![Ipld::Array(vec![Ipld::Integer(1)])]);
+    assert!(from_ipld::<BTreeMap<u8, u8>>(ipld).is_err());
+    let ipld = Ipld::Array(vec![Ipld::Integer(1)]);
+    assert!(from_ipld::<BTreeMap<u8, u8>>(ipld).is_err());
+  }
 }
@@ -3,8 +3,8 @@ use crate::{
     Cid,
     CID_SERDE_PRIVATE_IDENTIFIER,
   },
+  error::SerdeError,
   ipld::Ipld,
-  serde::error::SerdeError,
 };
 // use crate::SerdeError;
 
@@ -24,7 +24,18 @@ impl ser::Serialize for Ipld {
     match &self {
       Self::Null => serializer.serialize_none(),
       Self::Bool(value) => serializer.serialize_bool(*value),
-      Self::Number(value) => serializer.serialize_u64(*value),
+      Self::Integer(value) => {
+        if let Ok(value) = u64::try_from(*value) {
+          serializer.serialize_u64(value)
+        }
+        else if let Ok(value) = i64::try_from(*value) {
+          serializer.serialize_i64(value)
+        }
+        else {
+          serializer.serialize_i128(*value)
+        }
+      }
+      Self::Float(value) => serializer.serialize_f64(*value),
       Self::String(value) => serializer.serialize_str(value),
       Self::Bytes(value) => serializer.serialize_bytes(value),
       Self::Array(value) => serializer.collect_seq(value),
@@ -59,23 +70,28 @@ impl<'a> serde::Serializer for &'a Serializer {
   }
 
   #[inline]
-  fn serialize_i8(self, _value: i8) -> Result<Self::Ok, Self::Error> {
-    Err(ser::Error::custom("Negative numbers not supported"))
+  fn serialize_i8(self, value: i8) -> Result<Self::Ok, Self::Error> {
+    self.serialize_i64(i64::from(value))
   }
 
   #[inline]
-  fn serialize_i16(self, _value: i16) -> Result<Self::Ok, Self::Error> {
-    Err(ser::Error::custom("Negative numbers not supported"))
+  fn serialize_i16(self, value: i16) -> Result<Self::Ok, Self::Error> {
+    self.serialize_i64(i64::from(value))
   }
 
   #[inline]
-  fn serialize_i32(self, _value: i32) -> Result<Self::Ok, Self::Error> {
-    Err(ser::Error::custom("Negative numbers not supported"))
+  fn serialize_i32(self, value: i32) -> Result<Self::Ok, Self::Error> {
+    self.serialize_i64(i64::from(value))
   }
 
   #[inline]
-  fn serialize_i64(self, _value: i64) -> Result<Self::Ok, Self::Error> {
-    Err(ser::Error::custom("Negative numbers not supported"))
+  fn serialize_i64(self, value: i64) -> Result<Self::Ok, Self::Error> {
+    Ok(Self::Ok::Integer(value.into()))
+  }
+
+  #[inline]
+  fn serialize_i128(self, value: i128) -> Result<Self::Ok, Self::Error> {
+    Ok(Self::Ok::Integer(value))
   }
 
   #[inline]
@@ -95,17 +111,25 @@ impl<'a> serde::Serializer for &'a Serializer {
 
   #[inline]
   fn serialize_u64(self, value: u64) -> Result<Self::Ok, Self::Error> {
-    Ok(Self::Ok::Number(value))
+    Ok(Self::Ok::Integer(value.into()))
+  }
+
+  #[inline]
+  fn serialize_u128(self, value: u128) -> Result<Self::Ok, Self::Error> {
+    match i128::try_from(value) {
+      Ok(value) => Ok(Self::Ok::Integer(value)),
+      Err(_) => Err(ser::Error::custom("u128 value out of range")),
+    }
   }
 
   #[inline]
-  fn serialize_f32(self, _value: f32) -> Result<Self::Ok, Self::Error> {
-    Err(ser::Error::custom("Floats not supported"))
+  fn serialize_f32(self, value: f32) -> Result<Self::Ok, Self::Error> {
+    self.serialize_f64(f64::from(value))
   }
 
   #[inline]
-  fn serialize_f64(self, _value: f64) -> Result<Self::Ok, Self::Error> {
-    Err(ser::Error::custom("Floats not supported"))
+  fn serialize_f64(self, value: f64) -> Result<Self::Ok, Self::Error> {
+    Ok(Self::Ok::Float(value))
   }
 
   #[inline]
@@ -129,13 +153,8 @@ impl<'a> serde::Serializer for &'a Serializer {
   }
 
   #[inline]
-  fn serialize_some<T: ?Sized>(
-    self,
-    value: &T,
-  ) -> Result<Self::Ok, Self::Error>
-  where
-    T: Serialize,
-  {
+  fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+  where T: ?Sized + Serialize {
     value.serialize(self)
   }
 
@@ -161,29 +180,26 @@ impl<'a> serde::Serializer for &'a Serializer {
     Ok(Self::Ok::Array(vec![idx]))
   }
 
-  fn serialize_newtype_struct<T: ?Sized>(
+  fn serialize_newtype_struct<T>(
     self,
     name: &'static str,
     value: &T,
   ) -> Result<Self::Ok, Self::Error>
   where
-    T: Serialize,
+    T: ?Sized + Serialize,
   {
     let ipld = value.serialize(self);
     if name == CID_SERDE_PRIVATE_IDENTIFIER {
       if let Ok(Ipld::Bytes(bytes)) = ipld {
-        let cid = Cid::from_bytes(&mut &bytes[..]).unwrap();
-        // TODO: Proper error handling
-        // let cid = Cid::try_from(bytes)
-        //  .map_err(|err| ser::Error::custom(format!("Invalid CID: {}",
-        // err)))?;
+        let cid = Cid::from_bytes(&mut &bytes[..])
+          .map_err(|err| ser::Error::custom(format!("Invalid CID: {}", err)))?;
         return Ok(Self::Ok::Link(cid));
       }
     }
     ipld
   }
 
-  fn serialize_newtype_variant<T: ?Sized>(
+  fn serialize_newtype_variant<T>(
     self,
     _name: &'static str,
     variant_index: u32,
@@ -191,7 +207,7 @@ impl<'a> serde::Serializer for &'a Serializer {
     value: &T,
   ) -> Result<Self::Ok, Self::Error>
   where
-    T: Serialize,
+    T: ?Sized + Serialize,
   {
     let values =
       Vec::from([self.serialize_u32(variant_index)?, value.serialize(self)?]);
@@ -280,13 +296,8 @@ impl ser::SerializeSeq for SerializeVec {
   type Error = SerdeError;
   type Ok = Ipld;
 
-  fn serialize_element<T: ?Sized>(
-    &mut self,
-    value: &T,
-  ) -> Result<(), Self::Error>
-  where
-    T: ser::Serialize,
-  {
+  fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+  where T: ?Sized + ser::Serialize {
     self.vec.push(value.serialize(&Serializer)?);
     Ok(())
   }
@@ -300,13 +311,8 @@ impl ser::SerializeTuple for SerializeVec {
   type Error = SerdeError;
   type Ok = Ipld;
 
-  fn serialize_element<T: ?Sized>(
-    &mut self,
-    value: &T,
-  ) -> Result<(), Self::Error>
-  where
-    T: ser::Serialize,
-  {
+  fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+  where T: ?Sized + ser::Serialize {
     ser::SerializeSeq::serialize_element(self, value)
   }
 
@@ -317,13 +323,8 @@ impl ser::SerializeTupleStruct for SerializeVec {
   type Error = SerdeError;
   type Ok = Ipld;
 
-  fn serialize_field<T: ?Sized>(
-    &mut self,
-    value: &T,
-  ) -> Result<(), Self::Error>
-  where
-    T: ser::Serialize,
-  {
+  fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+  where T: ?Sized + ser::Serialize {
     ser::SerializeSeq::serialize_element(self, value)
   }
 
@@ -334,13 +335,8 @@ impl ser::SerializeTupleVariant for SerializeTupleVariant {
   type Error = SerdeError;
   type Ok = Ipld;
 
-  fn serialize_field<T: ?Sized>(
-    &mut self,
-    value: &T,
-  ) -> Result<(), Self::Error>
-  where
-    T: ser::Serialize,
-  {
+  fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+  where T: ?Sized + ser::Serialize {
     self.vec.push(value.serialize(&Serializer)?);
     Ok(())
   }
@@ -348,7 +344,7 @@ impl ser::SerializeTupleVariant for SerializeTupleVariant {
   fn end(self) -> Result<Self::Ok, Self::Error> {
     let mut vec = Vec::new();
     let mut args = self.vec.clone();
-    vec.push(Ipld::Number(self.idx as u64));
+    vec.push(Ipld::Integer(self.idx.into()));
     vec.append(&mut args);
     Ok(Ipld::Array(vec))
   }
@@ -358,20 +354,15 @@ impl ser::SerializeMap for SerializeMap {
   type Error = SerdeError;
   type Ok = Ipld;
 
-  fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Self::Error>
-  where T: ser::Serialize {
+  fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+  where T: ?Sized + ser::Serialize {
     let key = key.serialize(&Serializer)?;
     self.next_key = Some(key);
     Ok(())
   }
 
-  fn serialize_value<T: ?Sized>(
-    &mut self,
-    value: &T,
-  ) -> Result<(), Self::Error>
-  where
-    T: ser::Serialize,
-  {
+  fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+  where T: ?Sized + ser::Serialize {
     let key = self.next_key.take();
     // Panic because this indicates a bug in the program rather than an
     // expected failure.
@@ -414,13 +405,13 @@ impl<'a> ser::SerializeStruct for StructSerializer<'a> {
   type Ok = Ipld;
 
   #[inline]
-  fn serialize_field<T: ?Sized>(
+  fn serialize_field<T>(
     &mut self,
     key: &'static str,
     value: &T,
   ) -> Result<(), Self::Error>
   where
-    T: ser::Serialize,
+    T: ?Sized + ser::Serialize,
   {
     self.serialize_field_inner(key, value)?;
     Ok(())
@@ -442,20 +433,20 @@ impl<'a> ser::SerializeStructVariant for StructSerializer<'a> {
   type Error = SerdeError;
   type Ok = Ipld;
 
-  fn serialize_field<T: ?Sized>(
+  fn serialize_field<T>(
     &mut self,
     key: &'static str,
     value: &T,
   ) -> Result<(), Self::Error>
   where
-    T: ser::Serialize,
+    T: ?Sized + ser::Serialize,
   {
     self.serialize_field_inner(key, value)?;
     Ok(())
   }
 
   fn end(self) -> Result<Self::Ok, Self::Error> {
-    let mut vec = vec![Ipld::Number(self.variant_index as u64)];
+    let mut vec = vec![Ipld::Integer(self.variant_index.into())];
     let mut args = self.end_inner()?;
     vec.append(&mut args);
     Ok(Ipld::Array(vec))
@@ -0,0 +1,344 @@
+//! Places to keep blocks, keyed by CID.
+//!
+//! Stores index blocks by the multihash of their CID, so the same data
+//! reached through CIDs with different versions or codecs is stored once.
+
+use std::{
+  collections::BTreeMap,
+  fs,
+  io::{
+    self,
+    Write,
+  },
+  path::{
+    Path,
+    PathBuf,
+  },
+  sync::atomic::{
+    AtomicU64,
+    Ordering,
+  },
+};
+
+use crate::{
+  block::Block,
+  cid::Cid,
+  error::{
+    Error,
+    StoreError,
+  },
+  multibase::Base,
+};
+
+/// Storage for blocks.
+pub trait BlockStore {
+  /// Returns the block named by `cid`, if it is stored.
+  fn get(&self, cid: &Cid) -> Result<Option<Block>, Error>;
+
+  /// Stores `block`, after checking that its data matches its CID.
+  fn put(&mut self, block: Block) -> Result<(), Error>;
+
+  fn has(&self, cid: &Cid) -> Result<bool, Error>;
+
+  /// Removes the block named by `cid`, returning whether it was stored.
+  fn delete(&mut self, cid: &Cid) -> Result<bool, Error>;
+}
+
+/// Keeps blocks in memory, for tests and small DAGs.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+  blocks: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl MemoryStore {
+  pub fn new() -> Self { Self::default() }
+
+  pub fn len(&self) -> usize { self.blocks.len() }
+
+  pub fn is_empty(&self) -> bool { self.blocks.is_empty() }
+}
+
+impl BlockStore for MemoryStore {
+  fn get(&self, cid: &Cid) -> Result<Option<Block>, Error> {
+    let data = self.blocks.get(&cid.hash.to_bytes());
+    Ok(data.map(|data| Block::new_unchecked(cid.clone(), data.clone())))
+  }
+
+  fn put(&mut self, block: Block) -> Result<(), Error> {
+    block.verify()?;
+    let (cid, data) = block.into_inner();
+    self.blocks.insert(cid.hash.to_bytes(), data);
+    Ok(())
+  }
+
+  fn has(&self, cid: &Cid) -> Result<bool, Error> {
+    Ok(self.blocks.contains_key(&cid.hash.to_bytes()))
+  }
+
+  fn delete(&mut self, cid: &Cid) -> Result<bool, Error> {
+    Ok(self.blocks.remove(&cid.hash.to_bytes()).is_some())
+  }
+}
+
+// The layout of go-ipfs' flatfs: each block lives in a directory named after
+// the next-to-last two characters of its key
+const SHARDING: &str = "/repo/flatfs/shard/v1/next-to-last/2";
+
+// Where blocks are written before they are renamed into their shard, as in
+// go-ds-flatfs
+const TEMP_DIR: &str = ".temp";
+
+// Distinguishes the temporary files of concurrent writes in one process
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn io_error(e: io::Error) -> StoreError { StoreError::Io(e.kind()) }
+
+// Makes the entries created in `dir` durable. Directories can only be opened
+// and synced on Unix
+fn sync_dir(dir: &Path) -> io::Result<()> {
+  if cfg!(unix) {
+    fs::File::open(dir)?.sync_all()?;
+  }
+  Ok(())
+}
+
+/// Keeps blocks on disk in the sharded layout of flatfs. A block is stored in
+/// `<root>/<shard>/<key>.data`, where the key is its multihash in unpadded
+/// upper case base32 without the multibase code, and the shard is the two
+/// characters of the key before the last one.
+///
+/// Blocks are written to a temporary file in `<root>/.temp` first, synced
+/// and renamed into place, so a crash never leaves a partially written block
+/// behind. Opening the store removes the temporary files of writes that never
+/// finished, so a store must not be opened while another process writes to
+/// it. Blocks are verified against their CID when they are read back.
+#[derive(Debug, Clone)]
+pub struct FsStore {
+  root: PathBuf,
+}
+
+impl FsStore {
+  /// Opens the store in `root`, creating the directory if it is missing.
+  /// Temporary files left by earlier writes are removed.
+  pub fn open<P: AsRef<Path>>(root: P) -> Result<Self, Error> {
+    let root = root.as_ref().to_path_buf();
+    fs::create_dir_all(&root).map_err(io_error)?;
+    let shard_file = root.join("SHARDING");
+    match fs::read_to_string(&shard_file) {
+      Ok(sharding) => {
+        if sharding.trim_end() != SHARDING {
+          let found = sharding.trim_end().to_string();
+          return Err(StoreError::Sharding(found, SHARDING.into()).into());
+        }
+      }
+      Err(e) if e.kind() == io::ErrorKind::NotFound => {
+        fs::write(&shard_file, format!("{}\n", SHARDING)).map_err(io_error)?;
+      }
+      Err(e) => return Err(io_error(e).into()),
+    }
+    let temp = root.join(TEMP_DIR);
+    match fs::remove_dir_all(&temp) {
+      Ok(()) => (),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+      Err(e) => return Err(io_error(e).into()),
+    }
+    fs::create_dir(&temp).map_err(io_error)?;
+    Ok(FsStore { root })
+  }
+
+  pub fn root(&self) -> &Path { &self.root }
+
+  // Returns the shard directory and file of the block named by `cid`
+  fn path(&self, cid: &Cid) -> (PathBuf, PathBuf) {
+    let key = &Base::Base32Upper.encode(&cid.hash.to_bytes())[1..];
+    let end = key.len() - 1;
+    let dir = self.root.join(&key[end - 2..end]);
+    let file = dir.join(format!("{}.data", key));
+    (dir, file)
+  }
+}
+
+impl BlockStore for FsStore {
+  fn get(&self, cid: &Cid) -> Result<Option<Block>, Error> {
+    // Files may have been corrupted or replaced since they were written
+    match fs::read(self.path(cid).1) {
+      Ok(data) => Ok(Some(Block::new(cid.clone(), data)?)),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+      Err(e) => Err(io_error(e).into()),
+    }
+  }
+
+  fn put(&mut self, block: Block) -> Result<(), Error> {
+    block.verify()?;
+    let (dir, file) = self.path(block.cid());
+    if file.exists() {
+      return Ok(());
+    }
+    let new_shard = !dir.exists();
+    fs::create_dir_all(&dir).map_err(io_error)?;
+    let temp = self.root.join(TEMP_DIR).join(format!(
+      "put-{}-{}",
+      std::process::id(),
+      TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let write = || -> io::Result<()> {
+      let mut f = fs::File::create(&temp)?;
+      f.write_all(block.data())?;
+      f.sync_all()?;
+      fs::rename(&temp, &file)?;
+      sync_dir(&dir)?;
+      if new_shard {
+        sync_dir(&self.root)?;
+      }
+      Ok(())
+    };
+    write().map_err(|e| {
+      let _ = fs::remove_file(&temp);
+      io_error(e).into()
+    })
+  }
+
+  fn has(&self, cid: &Cid) -> Result<bool, Error> {
+    Ok(self.path(cid).1.exists())
+  }
+
+  fn delete(&mut self, cid: &Cid) -> Result<bool, Error> {
+    match fs::remove_file(self.path(cid).1) {
+      Ok(()) => Ok(true),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+      Err(e) => Err(io_error(e).into()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+
+  use crate::{
+    block::Block,
+    cid::Cid,
+    codec::{
+      DagCborCodec,
+      RawCodec,
+    },
+    error::{
+      BlockError,
+      Error,
+      StoreError,
+    },
+    ipld::Ipld,
+    multihash::Code,
+    store::{
+      BlockStore,
+      FsStore,
+      MemoryStore,
+    },
+  };
+
+  fn blocks() -> Vec<Block> {
+    (0..20)
+      .map(|i| {
+        let ipld =
+          Ipld::Array(vec![Ipld::Integer(i), Ipld::String("x".into())]);
+        Block::encode(&DagCborCodec, Code::Sha2_256, &ipld).unwrap()
+      })
+      .collect()
+  }
+
+  // Runs the same checks against any store
+  fn exercise<S: BlockStore>(store: &mut S) {
+    let blocks = blocks();
+    for block in &blocks {
+      assert!(!store.has(block.cid()).unwrap());
+      assert_eq!(store.get(block.cid()).unwrap(), None);
+      store.put(block.clone()).unwrap();
+      // Putting the same block again is harmless
+      store.put(block.clone()).unwrap();
+    }
+    for block in &blocks {
+      assert!(store.has(block.cid()).unwrap());
+      assert_eq!(store.get(block.cid()).unwrap().as_ref(), Some(block));
+    }
+    // Blocks are found by multihash, whatever the CID version or codec
+    let (cid, data) = blocks[0].clone().into_inner();
+    let raw = Cid::new_v1(0x55, cid.hash.clone());
+    assert_eq!(
+      store.get(&raw).unwrap(),
+      Some(Block::new_unchecked(raw, data.clone()))
+    );
+
+    let bad = Block::new_unchecked(cid.clone(), vec![0xf6]);
+    assert_eq!(
+      store.put(bad),
+      Err(Error::Block(BlockError::HashMismatch(cid.clone())))
+    );
+    assert_eq!(store.get(&cid).unwrap().unwrap().data(), data);
+
+    assert!(store.delete(&cid).unwrap());
+    assert!(!store.delete(&cid).unwrap());
+    assert!(!store.has(&cid).unwrap());
+    assert!(store.has(blocks[1].cid()).unwrap());
+  }
+
+  #[test]
+  fn memory_store() {
+    let mut store = MemoryStore::new();
+    exercise(&mut store);
+    assert_eq!(store.len(), 19);
+  }
+
+  #[test]
+  fn fs_store() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = FsStore::open(dir.path().join("blocks")).unwrap();
+    exercise(&mut store);
+    // Reopening finds the blocks written before
+    let store = FsStore::open(store.root()).unwrap();
+    assert!(store.has(blocks()[1].cid()).unwrap());
+  }
+
+  #[test]
+  fn fs_store_layout() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = FsStore::open(dir.path()).unwrap();
+    let empty =
+      Block::encode(&RawCodec, Code::Sha2_256, &Ipld::Bytes(vec![])).unwrap();
+    let cid = empty.cid().clone();
+    store.put(empty).unwrap();
+    // The key go-ipfs uses for the empty raw block
+    let key = "CIQOHMGEIKMPYHAUTL57JSEZN64SIJ5OIHSGJG4TJSSJLGI3PBJLQVI";
+    let file = dir.path().join("QV").join(format!("{}.data", key));
+    assert_eq!(fs::read(&file).unwrap(), b"");
+    assert_eq!(
+      fs::read_to_string(dir.path().join("SHARDING")).unwrap(),
+      "/repo/flatfs/shard/v1/next-to-last/2\n"
+    );
+    // No temporary files are left behind
+    assert_eq!(fs::read_dir(dir.path().join("QV")).unwrap().count(), 1);
+    assert_eq!(fs::read_dir(dir.path().join(".temp")).unwrap().count(), 0);
+
+    // Corrupted blocks are not returned as valid
+    fs::write(&file, b"tampered").unwrap();
+    assert_eq!(
+      store.get(&cid),
+      Err(Error::Block(BlockError::HashMismatch(cid)))
+    );
+
+    // Reopening removes the files of writes interrupted by a crash
+    let stale = dir.path().join(".temp").join("put-1-0");
+    fs::write(&stale, b"partial").unwrap();
+    FsStore::open(dir.path()).unwrap();
+    assert!(!stale.exists());
+
+    fs::write(dir.path().join("SHARDING"), "/repo/flatfs/shard/v1/prefix/2")
+      .unwrap();
+    assert_eq!(
+      FsStore::open(dir.path()).unwrap_err(),
+      Error::Store(StoreError::Sharding(
+        "/repo/flatfs/shard/v1/prefix/2".into(),
+        "/repo/flatfs/shard/v1/next-to-last/2".into()
+      ))
+    );
+  }
+}
@@ -0,0 +1,493 @@
+//! Walking a DAG by following the links in its blocks, and resolving paths
+//! across them.
+//!
+//! Blocks are fetched through a loader, any closure from a [`Cid`] to the
+//! bytes of its block, or `None` when the block is missing. [`store_loader`]
+//! turns a [`BlockStore`] into one. Loaded bytes are checked against their
+//! CID before being decoded with the codec the CID names.
+
+use std::collections::{
+  BTreeSet,
+  VecDeque,
+};
+
+use crate::{
+  block::Block,
+  cid::Cid,
+  codec::Registry,
+  error::{
+    Error,
+    TraversalError,
+  },
+  ipld::{
+    path_segments,
+    push_segment,
+    Ipld,
+  },
+  multihash::Code,
+  store::BlockStore,
+};
+
+/// The order blocks are visited in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+  /// Each block's links are visited before its siblings.
+  DepthFirst,
+  /// Blocks are visited by their distance from the root.
+  BreadthFirst,
+}
+
+/// Returns a loader reading from `store`.
+pub fn store_loader<S: BlockStore + ?Sized>(
+  store: &S,
+) -> impl FnMut(&Cid) -> Result<Option<Vec<u8>>, Error> + '_ {
+  |cid| Ok(store.get(cid)?.map(|block| block.into_inner().1))
+}
+
+// Loads, checks and decodes the block named by `cid`
+fn load<L>(
+  loader: &mut L,
+  registry: &Registry,
+  cid: &Cid,
+) -> Result<Ipld, Error>
+where
+  L: FnMut(&Cid) -> Result<Option<Vec<u8>>, Error>,
+{
+  // Identity hashed CIDs carry their block inline
+  let data = if cid.hash.code() == Code::Identity.code() {
+    cid.hash.digest().to_vec()
+  }
+  else {
+    loader(cid)?.ok_or_else(|| TraversalError::MissingBlock(cid.clone()))?
+  };
+  Block::new(cid.clone(), data)?.decode_with(registry)
+}
+
+/// Iterates over the blocks reachable from a root, each one decoded and
+/// visited once however many links lead to it. Memory grows with the number
+/// of blocks visited and of the links in them, never with the number of
+/// paths through the DAG.
+///
+/// The iteration stops after the first error.
+pub struct Walker<L> {
+  loader: L,
+  registry: Registry,
+  order: Order,
+  pending: VecDeque<Cid>,
+  // Every CID visited, and breadth first also every CID queued
+  seen: BTreeSet<Cid>,
+  done: bool,
+}
+
+impl<L> Walker<L>
+where L: FnMut(&Cid) -> Result<Option<Vec<u8>>, Error>
+{
+  pub fn new(root: Cid, order: Order, loader: L) -> Self {
+    let mut seen = BTreeSet::new();
+    if order == Order::BreadthFirst {
+      seen.insert(root.clone());
+    }
+    Walker {
+      loader,
+      registry: Registry::default(),
+      order,
+      pending: VecDeque::from([root]),
+      seen,
+      done: false,
+    }
+  }
+
+  /// Decodes blocks with the codecs of `registry` instead of the built-in
+  /// ones.
+  pub fn with_registry(mut self, registry: Registry) -> Self {
+    self.registry = registry;
+    self
+  }
+
+  fn visit(&mut self) -> Result<Option<(Cid, Ipld)>, Error> {
+    let cid = loop {
+      match self.pending.pop_front() {
+        None => return Ok(None),
+        // Depth first, a block may be queued again before it is visited
+        Some(cid) if self.order == Order::DepthFirst => {
+          if self.seen.insert(cid.clone()) {
+            break cid;
+          }
+        }
+        Some(cid) => break cid,
+      }
+    };
+    let ipld = load(&mut self.loader, &self.registry, &cid)?;
+    let links = ipld.links();
+    match self.order {
+      Order::BreadthFirst => {
+        for link in links {
+          if self.seen.insert(link.clone()) {
+            self.pending.push_back(link.clone());
+          }
+        }
+      }
+      Order::DepthFirst => {
+        for link in links.into_iter().rev() {
+          if !self.seen.contains(link) {
+            self.pending.push_front(link.clone());
+          }
+        }
+      }
+    }
+    Ok(Some((cid, ipld)))
+  }
+}
+
+impl<L> Iterator for Walker<L>
+where L: FnMut(&Cid) -> Result<Option<Vec<u8>>, Error>
+{
+  type Item = Result<(Cid, Ipld), Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+    let next = self.visit().transpose();
+    self.done = !matches!(next, Some(Ok(_)));
+    next
+  }
+}
+
+/// Returns the CIDs of every block reachable from `root`, root first and
+/// in depth first order.
+pub fn reachable<L>(root: Cid, loader: L) -> Result<Vec<Cid>, Error>
+where L: FnMut(&Cid) -> Result<Option<Vec<u8>>, Error> {
+  Walker::new(root, Order::DepthFirst, loader).map(|next| Ok(next?.0)).collect()
+}
+
+/// The value found at the end of a path, with the CIDs of the blocks the path
+/// went through, root first. The blocks prove the value to anyone holding
+/// the root CID.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolved {
+  pub value: Ipld,
+  pub cids: Vec<Cid>,
+}
+
+/// Looks up paths that may lead through links into other blocks.
+pub struct Resolver<L> {
+  loader: L,
+  registry: Registry,
+}
+
+impl<L> Resolver<L>
+where L: FnMut(&Cid) -> Result<Option<Vec<u8>>, Error>
+{
+  pub fn new(loader: L) -> Self {
+    Resolver { loader, registry: Registry::default() }
+  }
+
+  /// Decodes blocks with the codecs of `registry` instead of the built-in
+  /// ones.
+  pub fn with_registry(mut self, registry: Registry) -> Self {
+    self.registry = registry;
+    self
+  }
+
+  /// Resolves the `/` separated `path` from the block named by `root`, as
+  /// [`Ipld::get_path`] would, except that links are followed, including
+  /// one at the end of the path.
+  pub fn resolve(&mut self, root: &Cid, path: &str) -> Result<Resolved, Error> {
+    let mut cids = vec![root.clone()];
+    let mut value = load(&mut self.loader, &self.registry, root)?;
+    let mut resolved = String::new();
+    for segment in path_segments(path) {
+      value = self.follow(value, &mut cids)?;
+      value = value.get_segment(segment, &resolved)?.clone();
+      push_segment(&mut resolved, segment);
+    }
+    let value = self.follow(value, &mut cids)?;
+    Ok(Resolved { value, cids })
+  }
+
+  // Replaces a link with the root of the block it names
+  fn follow(
+    &mut self,
+    mut value: Ipld,
+    cids: &mut Vec<Cid>,
+  ) -> Result<Ipld, Error> {
+    while let Ipld::Link(cid) = value {
+      value = load(&mut self.loader, &self.registry, &cid)?;
+      cids.push(cid);
+    }
+    Ok(value)
+  }
+}
+
+/// Resolves `path` from `root`, loading blocks with `loader`.
+pub fn resolve<L>(
+  root: &Cid,
+  path: &str,
+  loader: L,
+) -> Result<Resolved, Error>
+where
+  L: FnMut(&Cid) -> Result<Option<Vec<u8>>, Error>,
+{
+  Resolver::new(loader).resolve(root, path)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    block::Block,
+    cid::Cid,
+    codec::{
+      DagCborCodec,
+      DagJsonCodec,
+      DagPbCodec,
+      RawCodec,
+    },
+    dag_pb::{
+      PbLink,
+      PbNode,
+    },
+    error::{
+      BlockError,
+      Error,
+      PathError,
+      TraversalError,
+    },
+    ipld::Ipld,
+    multihash::{
+      Code,
+      Multihash,
+    },
+    store::{
+      BlockStore,
+      MemoryStore,
+    },
+    traversal::{
+      reachable,
+      resolve,
+      store_loader,
+      Order,
+      Resolved,
+      Walker,
+    },
+  };
+
+  fn put(store: &mut MemoryStore, ipld: Ipld) -> Cid {
+    let block = Block::encode(&DagCborCodec, Code::Sha2_256, &ipld).unwrap();
+    let cid = block.cid().clone();
+    store.put(block).unwrap();
+    cid
+  }
+
+  fn node(name: &str, links: &[&Cid]) -> Ipld {
+    Ipld::to_object(vec![
+      (
+        "links".into(),
+        Ipld::Array(
+          links.iter().map(|cid| Ipld::Link((*cid).clone())).collect(),
+        ),
+      ),
+      ("name".into(), Ipld::String(name.into())),
+    ])
+  }
+
+  // A diamond, root -> a, b -> c, with an extra link from root to c
+  fn diamond(store: &mut MemoryStore) -> [Cid; 4] {
+    let c = put(store, node("c", &[]));
+    let a = put(store, node("a", &[&c]));
+    let b = put(store, node("b", &[&c]));
+    let root = put(store, node("root", &[&a, &b, &c]));
+    [root, a, b, c]
+  }
+
+  fn names<L>(walker: Walker<L>) -> Vec<String>
+  where L: FnMut(&Cid) -> Result<Option<Vec<u8>>, Error> {
+    walker
+      .map(|next| match next.unwrap().1 {
+        Ipld::Object(map) => match &map["name"] {
+          Ipld::String(name) => name.clone(),
+          _ => unreachable!(),
+        },
+        _ => unreachable!(),
+      })
+      .collect()
+  }
+
+  #[test]
+  fn visit_orders() {
+    let mut store = MemoryStore::new();
+    let [root, a, b, c] = diamond(&mut store);
+    let dfs =
+      Walker::new(root.clone(), Order::DepthFirst, store_loader(&store));
+    assert_eq!(names(dfs), ["root", "a", "c", "b"]);
+    let bfs =
+      Walker::new(root.clone(), Order::BreadthFirst, store_loader(&store));
+    assert_eq!(names(bfs), ["root", "a", "b", "c"]);
+    assert_eq!(reachable(root.clone(), store_loader(&store)).unwrap(), [
+      root, a, c, b
+    ]);
+  }
+
+  #[test]
+  fn mixed_codecs() {
+    // A dag-pb directory holding a raw file and a dag-json document, which
+    // links back to the file
+    let mut store = MemoryStore::new();
+    let file =
+      Block::encode(&RawCodec, Code::Sha2_256, &Ipld::Bytes(b"hi".to_vec()))
+        .unwrap();
+    let doc =
+      Ipld::to_object(vec![("file".into(), Ipld::Link(file.cid().clone()))]);
+    let doc = Block::encode(&DagJsonCodec, Code::Blake3, &doc).unwrap();
+    let dir = PbNode {
+      links: vec![
+        PbLink {
+          hash: doc.cid().clone(),
+          name: Some("doc".into()),
+          tsize: None,
+        },
+        PbLink {
+          hash: file.cid().clone(),
+          name: Some("file".into()),
+          tsize: Some(2),
+        },
+      ],
+      data: None,
+    };
+    let dir = Block::encode(&DagPbCodec, Code::Sha2_256, &dir.into()).unwrap();
+    for block in [&file, &doc, &dir] {
+      store.put(block.clone()).unwrap();
+    }
+    let walker =
+      Walker::new(dir.cid().clone(), Order::DepthFirst, store_loader(&store));
+    let visited: Vec<Cid> = walker.map(|next| next.unwrap().0).collect();
+    assert_eq!(visited, [dir.cid(), doc.cid(), file.cid()].map(Cid::clone));
+  }
+
+  #[test]
+  fn missing_blocks() {
+    let mut store = MemoryStore::new();
+    let [root, _, b, _] = diamond(&mut store);
+    store.delete(&b).unwrap();
+    let results: Vec<_> =
+      Walker::new(root.clone(), Order::BreadthFirst, store_loader(&store))
+        .collect();
+    assert_eq!(results.len(), 3);
+    assert_eq!(
+      results[2],
+      Err(Error::Traversal(TraversalError::MissingBlock(b.clone())))
+    );
+    assert_eq!(
+      reachable(root, store_loader(&store)),
+      Err(Error::Traversal(TraversalError::MissingBlock(b)))
+    );
+  }
+
+  #[test]
+  fn closure_loaders() {
+    let mut store = MemoryStore::new();
+    let [root, a, ..] = diamond(&mut store);
+    // Loaded bytes are checked against the CID
+    let lying = |cid: &Cid| {
+      if *cid == a {
+        return Ok(Some(b"\xf6".to_vec()));
+      }
+      Ok(store.get(cid)?.map(|block| block.data().to_vec()))
+    };
+    let err = reachable(root.clone(), lying).unwrap_err();
+    assert_eq!(err, Error::Block(BlockError::HashMismatch(a)));
+
+    // Counting loads shows every block is loaded once
+    let mut loads = 0;
+    let counting = |cid: &Cid| {
+      loads += 1;
+      Ok(store.get(cid)?.map(|block| block.data().to_vec()))
+    };
+    assert_eq!(reachable(root, counting).unwrap().len(), 4);
+    assert_eq!(loads, 4);
+  }
+
+  #[test]
+  fn identity_links() {
+    // Identity hashed blocks are never loaded
+    let leaf =
+      Block::encode(&DagCborCodec, Code::Identity, &Ipld::Integer(7)).unwrap();
+    assert_eq!(leaf.cid().hash, Multihash::hash(Code::Identity, &[0x07]));
+    let mut store = MemoryStore::new();
+    let root = put(&mut store, node("root", &[leaf.cid()]));
+    let visited: Vec<_> =
+      Walker::new(root, Order::DepthFirst, store_loader(&store))
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(visited[1], (leaf.cid().clone(), Ipld::Integer(7)));
+  }
+
+  #[test]
+  fn resolve_paths() {
+    let mut store = MemoryStore::new();
+    let [root, a, _, c] = diamond(&mut store);
+    let name = |s: &str| Ipld::String(s.into());
+    let at = |path: &str| resolve(&root, path, store_loader(&store));
+    assert_eq!(
+      at("name"),
+      Ok(Resolved { value: name("root"), cids: vec![root.clone()] })
+    );
+    assert_eq!(
+      at("links/0/links/0/name"),
+      Ok(Resolved {
+        value: name("c"),
+        cids: vec![root.clone(), a.clone(), c.clone()]
+      })
+    );
+    // A link at the end of the path is followed too
+    let Resolved { value, cids } = at("/links/0/").unwrap();
+    assert_eq!(value, store.get(&a).unwrap().unwrap().decode().unwrap());
+    assert_eq!(cids, [root.clone(), a.clone()]);
+    assert_eq!(at("").unwrap().cids, [root]);
+  }
+
+  #[test]
+  fn resolve_errors() {
+    let mut store = MemoryStore::new();
+    let [root, a, _, c] = diamond(&mut store);
+    let at = |path: &str| resolve(&root, path, store_loader(&store));
+    // Paths in errors start from the root
+    assert_eq!(
+      at("links/0/nope"),
+      Err(Error::Path(PathError::MissingKey {
+        key: "nope".into(),
+        path: "links/0".into()
+      }))
+    );
+    assert_eq!(
+      at("links/0/links/1"),
+      Err(Error::Path(PathError::IndexOutOfRange {
+        index: 1,
+        len: 1,
+        path: "links/0/links".into()
+      }))
+    );
+    assert_eq!(
+      at("links/x"),
+      Err(Error::Path(PathError::InvalidIndex {
+        segment: "x".into(),
+        path: "links".into()
+      }))
+    );
+    assert_eq!(
+      at("name/0"),
+      Err(Error::Path(PathError::NotTraversable {
+        segment: "0".into(),
+        kind: "string",
+        path: "name".into()
+      }))
+    );
+    store.delete(&c).unwrap();
+    let at = |path: &str| resolve(&root, path, store_loader(&store));
+    assert_eq!(
+      at("links/0/links/0/name"),
+      Err(Error::Traversal(TraversalError::MissingBlock(c)))
+    );
+    assert_eq!(at("links/0/name").unwrap().cids, [root.clone(), a]);
+  }
+}
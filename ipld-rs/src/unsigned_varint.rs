@@ -1,63 +1,197 @@
-use std::convert::TryFrom;
-
-// TODO: rewrite with bitvec
-// TODO: rewrite with iterators
-
-pub fn to_varint(mut y: u64) -> Vec<u8> {
-  let mut result: Vec<u8> = vec![];
-  loop {
-    let b: u8 = u8::try_from(y % 128).unwrap();
-    y /= 128;
-    if y == 0 {
-      result.push(b);
-      break;
-    }
-    else {
-      result.push(b + 128);
-    }
+//! The multiformats unsigned varint: little-endian groups of 7 bits, with the
+//! high bit of each byte set on all but the last. Decoding follows the spec
+//! strictly, so every value has exactly one accepted encoding.
+
+use crate::error::VarintError;
+use std::io;
+
+/// The longest varint a decoder accepts. Nine bytes hold 63 bits, so only
+/// values below 2^63 can be read back.
+pub const MAX_LEN: usize = 9;
+
+/// The largest value that fits in `MAX_LEN` bytes.
+pub const MAX_VALUE: u64 = (1 << 63) - 1;
+
+/// The size of the buffer [`encode_into`] takes, enough for any `u64`.
+pub const BUF_LEN: usize = 10;
+
+/// Writes `n` into `buf`, returning the bytes used. Values above `MAX_VALUE`
+/// would take a tenth byte that no decoder accepts, so they are rejected.
+pub fn encode_into(
+  mut n: u64,
+  buf: &mut [u8; BUF_LEN],
+) -> Result<&[u8], VarintError> {
+  if n > MAX_VALUE {
+    return Err(VarintError::TooLarge(n));
   }
-  result
+  let mut i = 0;
+  while n >= 0x80 {
+    buf[i] = (n as u8) | 0x80;
+    n >>= 7;
+    i += 1;
+  }
+  buf[i] = n as u8;
+  Ok(&buf[..=i])
 }
 
-pub fn from_varint(bytes: &[u8]) -> Result<u64, ()> {
-  if bytes.is_empty() {
-    return Err(());
-  }
+pub fn to_varint(n: u64) -> Result<Vec<u8>, VarintError> {
+  let mut buf = [0; BUF_LEN];
+  encode_into(n, &mut buf).map(|bytes| bytes.to_vec())
+}
+
+/// The number of bytes `n` takes as a varint.
+pub fn encoded_len(n: u64) -> usize {
+  (64 - n.leading_zeros() as usize).max(1).div_ceil(7)
+}
+
+/// Decodes a varint from the start of `bytes`, returning it with the input
+/// that follows it.
+pub fn decode(bytes: &[u8]) -> Result<(u64, &[u8]), VarintError> {
   let mut result: u64 = 0;
-  for (i, item) in bytes.iter().enumerate() {
-    let b = (*item as u64 % 128) << (i * 7);
-    result += b as u64;
-    if item / 128 == 0 {
-      break;
+  for (i, b) in bytes.iter().enumerate() {
+    if i == MAX_LEN {
+      return Err(VarintError::Overflow { offset: i });
+    }
+    result |= ((b & 0x7f) as u64) << (i * 7);
+    if b & 0x80 == 0 {
+      // A final zero group could have been left off
+      if *b == 0 && i > 0 {
+        return Err(VarintError::NotMinimal { offset: i });
+      }
+      return Ok((result, &bytes[i + 1..]));
     }
   }
-  Ok(result)
+  Err(VarintError::UnexpectedEof { offset: bytes.len() })
 }
 
-pub fn varint_read_u64<R: std::io::Read>(r: &mut R) -> Result<u64, String> {
-  let mut b = [0u8; 8];
-  for i in 0..b.len() {
-    let n = r.read(&mut (b[i..i + 1])).unwrap();
-    if n == 0 {
-      return Err("Varint decode error".into());
-    }
-    else if b[i] & 0x80 == 0 {
-      return Ok(from_varint(&b[..=i]).unwrap());
+/// Decodes a varint from the start of `bytes`, ignoring what follows it.
+pub fn from_varint(bytes: &[u8]) -> Result<u64, VarintError> {
+  decode(bytes).map(|(n, _)| n)
+}
+
+/// Reads a varint one byte at a time, so nothing after it is consumed.
+pub fn varint_read_u64<R: io::Read>(r: &mut R) -> Result<u64, VarintError> {
+  let mut buf = [0u8; MAX_LEN];
+  for i in 0..MAX_LEN {
+    r.read_exact(&mut buf[i..=i]).map_err(|e| match e.kind() {
+      io::ErrorKind::UnexpectedEof => VarintError::UnexpectedEof { offset: i },
+      kind => VarintError::Io(kind),
+    })?;
+    if buf[i] & 0x80 == 0 {
+      return decode(&buf[..=i]).map(|(n, _)| n);
     }
   }
-  Err("Varint overflow error".into())
+  Err(VarintError::Overflow { offset: MAX_LEN })
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::unsigned_varint::{
-    from_varint,
-    to_varint,
+  use crate::{
+    error::VarintError,
+    unsigned_varint::{
+      decode,
+      encode_into,
+      encoded_len,
+      from_varint,
+      to_varint,
+      varint_read_u64,
+      BUF_LEN,
+      MAX_VALUE,
+    },
   };
 
   #[test]
   fn varint_roundtrip() {
-    assert_eq!(from_varint(&vec![160, 141, 6]).unwrap(), 100000);
-    assert_eq!(from_varint(&to_varint(50)).unwrap(), 50);
+    assert_eq!(from_varint(&[160, 141, 6]).unwrap(), 100000);
+    assert_eq!(from_varint(&to_varint(50).unwrap()).unwrap(), 50);
+  }
+
+  #[test]
+  fn spec_vectors() {
+    // From the multiformats unsigned-varint README
+    let cases: [(u64, &[u8]); 8] = [
+      (1, &[0x01]),
+      (127, &[0x7f]),
+      (128, &[0x80, 0x01]),
+      (255, &[0xff, 0x01]),
+      (300, &[0xac, 0x02]),
+      (16384, &[0x80, 0x80, 0x01]),
+      (0, &[0x00]),
+      ((1 << 63) - 1, &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]),
+    ];
+    for (n, bytes) in cases {
+      let mut buf = [0; BUF_LEN];
+      assert_eq!(encode_into(n, &mut buf), Ok(bytes));
+      assert_eq!(decode(bytes), Ok((n, &[][..])));
+      assert_eq!(varint_read_u64(&mut &bytes[..]), Ok(n));
+    }
+  }
+
+  #[test]
+  fn boundaries() {
+    // Every length from one to nine bytes, at both ends of its range
+    for bits in (7..=63).step_by(7) {
+      let len = bits / 7;
+      for n in [(1u64 << (bits - 7)) - u64::from(bits == 7), (1 << bits) - 1] {
+        let mut buf = [0; BUF_LEN];
+        let bytes = encode_into(n, &mut buf).unwrap();
+        assert_eq!(bytes.len(), len);
+        assert_eq!(encoded_len(n), len);
+        assert_eq!(to_varint(n).unwrap(), bytes);
+        assert_eq!(decode(bytes).unwrap().0, n);
+        assert_eq!(varint_read_u64(&mut &bytes[..]).unwrap(), n);
+      }
+    }
+    // Anything from 2^63 would need ten bytes, which decoders reject, so it
+    // cannot be encoded either
+    assert_eq!(MAX_VALUE, (1 << 63) - 1);
+    for n in [MAX_VALUE + 1, u64::MAX] {
+      let mut buf = [0; BUF_LEN];
+      assert_eq!(encode_into(n, &mut buf), Err(VarintError::TooLarge(n)));
+      assert_eq!(to_varint(n), Err(VarintError::TooLarge(n)));
+    }
+    let ten = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01];
+    assert_eq!(decode(&ten), Err(VarintError::Overflow { offset: 9 }));
+  }
+
+  #[test]
+  fn decode_returns_rest() {
+    assert_eq!(decode(&[0xac, 0x02, 0x01, 0x02]), Ok((300, &[0x01, 0x02][..])));
+    let mut r = &[0x80, 0x01, 0xff][..];
+    assert_eq!(varint_read_u64(&mut r), Ok(128));
+    assert_eq!(r, [0xff]);
+  }
+
+  #[test]
+  fn varint_malformed() {
+    assert_eq!(from_varint(&[]), Err(VarintError::UnexpectedEof { offset: 0 }));
+    assert_eq!(
+      from_varint(&[0x80, 0x80]),
+      Err(VarintError::UnexpectedEof { offset: 2 })
+    );
+    assert_eq!(
+      from_varint(&[0xff; 11]),
+      Err(VarintError::Overflow { offset: 9 })
+    );
+    assert_eq!(
+      varint_read_u64(&mut &[0xa0, 0x8d][..]),
+      Err(VarintError::UnexpectedEof { offset: 2 })
+    );
+    assert_eq!(
+      varint_read_u64(&mut &[0xff; 9][..]),
+      Err(VarintError::Overflow { offset: 9 })
+    );
+  }
+
+  #[test]
+  fn non_minimal() {
+    for bytes in [&[0x80, 0x00][..], &[0x81, 0x00], &[0x80, 0x80, 0x00]] {
+      let offset = bytes.len() - 1;
+      assert_eq!(decode(bytes), Err(VarintError::NotMinimal { offset }));
+      assert_eq!(
+        varint_read_u64(&mut &bytes[..]),
+        Err(VarintError::NotMinimal { offset })
+      );
+    }
   }
 }
@@ -0,0 +1,75 @@
+//! Exercises the public surface of the crate the way a downstream consumer
+//! would, through the crate root and the prelude only.
+
+use ipld_rs::prelude::*;
+use serde::{
+  Deserialize,
+  Serialize,
+};
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Point {
+  x: u32,
+  y: u32,
+}
+
+#[test]
+fn dag_cbor_roundtrip() {
+  let ipld = Ipld::Array(vec![
+    Ipld::Null,
+    Ipld::Bool(false),
+    Ipld::Integer(0x10000),
+    Ipld::Integer(-42),
+    Ipld::Float(1.5),
+    Ipld::String("Hello".into()),
+    Ipld::Bytes(vec![0, 8, 4, 0]),
+    Ipld::to_object(vec![("Hello".into(), Ipld::String("World".into()))]),
+  ]);
+  let bytes = to_dag_cbor(&ipld).unwrap();
+  assert_eq!(ipld, from_dag_cbor(&mut &bytes[..]).unwrap());
+}
+
+#[test]
+fn cid_of_encoded_block() {
+  let bytes = to_dag_cbor(&Ipld::String("block".into())).unwrap();
+  let hash = Multihash::sha3_256(&bytes);
+  assert_eq!(hash.code(), 0x16);
+  assert_eq!(hash.size(), 32);
+  assert_eq!(hash.digest().len(), 32);
+  let cid = Cid::new(1, 0x71, hash);
+  assert_eq!(cid, Cid::from_bytes(&mut &cid.to_bytes().unwrap()[..]).unwrap());
+}
+
+#[test]
+fn block_of_ipld() {
+  use ipld_rs::{
+    codec::DagCborCodec,
+    multihash::Code,
+  };
+  let ipld = Ipld::String("block".into());
+  let block = Block::encode(&DagCborCodec, Code::Sha3_256, &ipld).unwrap();
+  assert_eq!(
+    block.cid().hash,
+    Multihash::sha3_256(&to_dag_cbor(&ipld).unwrap())
+  );
+  assert!(block.verify().is_ok());
+  assert_eq!(block.decode().unwrap(), ipld);
+}
+
+#[test]
+fn serde_roundtrip() {
+  let point = Point { x: 1, y: 2 };
+  let ipld = to_ipld(&point).unwrap();
+  assert_eq!(ipld, Ipld::Array(vec![Ipld::Integer(1), Ipld::Integer(2)]));
+  assert_eq!(point, from_ipld::<Point>(ipld).unwrap());
+}
+
+#[test]
+fn root_reexports() {
+  let ipld = ipld_rs::Ipld::Bool(true);
+  let bytes = ipld_rs::to_dag_cbor(&ipld).unwrap();
+  assert_eq!(ipld, ipld_rs::from_dag_cbor(&mut &bytes[..]).unwrap());
+  let err: Result<ipld_rs::Ipld, ipld_rs::SerdeError> =
+    ipld_rs::to_ipld(u128::MAX);
+  assert!(err.is_err());
+}
@@ -0,0 +1,53 @@
+//! Checks that the DAG-CBOR codec produces byte-identical output to other
+//! IPLD implementations.
+
+mod fixtures;
+
+use fixtures::{
+  fixtures,
+  hex,
+};
+use ipld_rs::{
+  dag_cbor::{
+    deserialize_strict,
+    encoded_len,
+    serialize_into,
+  },
+  from_dag_cbor,
+  to_dag_cbor,
+};
+
+#[test]
+fn encode_fixtures() {
+  for fixture in fixtures() {
+    let bytes = to_dag_cbor(&fixture.ipld).unwrap();
+    assert_eq!(bytes, hex(fixture.dag_cbor), "encoding `{}`", fixture.name);
+  }
+}
+
+#[test]
+fn decode_fixtures() {
+  for fixture in fixtures() {
+    let ipld = from_dag_cbor(&mut &hex(fixture.dag_cbor)[..]).unwrap();
+    assert_eq!(ipld, fixture.ipld, "decoding `{}`", fixture.name);
+  }
+}
+
+#[test]
+fn fixtures_are_canonical() {
+  for fixture in fixtures() {
+    let ipld = deserialize_strict(&mut &hex(fixture.dag_cbor)[..]).unwrap();
+    assert_eq!(ipld, fixture.ipld, "strictly decoding `{}`", fixture.name);
+  }
+}
+
+#[test]
+fn stream_fixtures() {
+  for fixture in fixtures() {
+    let mut bytes = Vec::new();
+    serialize_into(&fixture.ipld, &mut bytes).unwrap();
+    assert_eq!(bytes, hex(fixture.dag_cbor), "streaming `{}`", fixture.name);
+    let len = encoded_len(&fixture.ipld).unwrap();
+    assert_eq!(len, bytes.len() as u64, "length of `{}`", fixture.name);
+  }
+}
@@ -0,0 +1,44 @@
+//! Checks that the DAG-JSON codec agrees with the DAG-CBOR codec on the shared
+//! fixtures, and with the canonical DAG-JSON form of each of them.
+
+mod fixtures;
+
+use fixtures::{
+  fixtures,
+  hex,
+};
+use ipld_rs::{
+  from_dag_cbor,
+  from_dag_json,
+  to_dag_json,
+};
+
+#[test]
+fn encode_fixtures() {
+  for fixture in fixtures() {
+    let bytes = to_dag_json(&fixture.ipld).unwrap();
+    assert_eq!(
+      String::from_utf8(bytes).unwrap(),
+      fixture.dag_json,
+      "encoding `{}`",
+      fixture.name
+    );
+  }
+}
+
+#[test]
+fn decode_fixtures() {
+  for fixture in fixtures() {
+    let ipld = from_dag_json(&mut fixture.dag_json.as_bytes()).unwrap();
+    assert_eq!(ipld, fixture.ipld, "decoding `{}`", fixture.name);
+  }
+}
+
+#[test]
+fn dag_cbor_to_dag_json() {
+  for fixture in fixtures() {
+    let ipld = from_dag_cbor(&mut &hex(fixture.dag_cbor)[..]).unwrap();
+    let json = to_dag_json(&ipld).unwrap();
+    assert_eq!(from_dag_json(&mut &json[..]).unwrap(), ipld);
+  }
+}
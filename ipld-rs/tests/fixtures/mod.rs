@@ -0,0 +1,151 @@
+//! Cross-implementation DAG-CBOR and DAG-JSON vectors shared by the codec test
+//! suites.
+//!
+//! The scalar, string, array and map vectors are taken from RFC 8949
+//! Appendix A and from the Lean test suite (`Tests/DagCbor.lean`); the map
+//! ordering vectors follow the DAG-CBOR spec, which sorts keys by length first
+//! and bytewise second, and agree with go-ipld-prime and js-dag-cbor. The
+//! DAG-JSON forms sort keys bytewise and write floats in their shortest
+//! round-tripping form.
+
+#![allow(dead_code)]
+
+use ipld_rs::{
+  Cid,
+  Ipld,
+};
+
+pub struct Fixture {
+  pub name: &'static str,
+  pub ipld: Ipld,
+  pub dag_cbor: &'static str,
+  pub dag_json: &'static str,
+}
+
+pub fn hex(s: &str) -> Vec<u8> {
+  (0..s.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+    .collect()
+}
+
+fn fixture(
+  name: &'static str,
+  ipld: Ipld,
+  dag_cbor: &'static str,
+  dag_json: &'static str,
+) -> Fixture {
+  Fixture { name, ipld, dag_cbor, dag_json }
+}
+
+fn object(entries: Vec<(&str, Ipld)>) -> Ipld {
+  Ipld::to_object(entries.into_iter().map(|(k, v)| (k.into(), v)).collect())
+}
+
+fn string(s: &str) -> Ipld { Ipld::String(s.into()) }
+
+pub fn fixtures() -> Vec<Fixture> {
+  let cid = Cid::try_from(hex(
+    "01711220f88bc853804cf294fe417e4fa83028689fcdb1b1592c5102e1474dbc200fab8b",
+  ))
+  .unwrap();
+  vec![
+    fixture("null", Ipld::Null, "f6", "null"),
+    fixture("true", Ipld::Bool(true), "f5", "true"),
+    fixture("false", Ipld::Bool(false), "f4", "false"),
+    fixture("int-0", Ipld::Integer(0), "00", "0"),
+    fixture("int-23", Ipld::Integer(23), "17", "23"),
+    fixture("int-24", Ipld::Integer(24), "1818", "24"),
+    fixture("int-255", Ipld::Integer(255), "18ff", "255"),
+    fixture("int-1000", Ipld::Integer(1000), "1903e8", "1000"),
+    fixture("int-65535", Ipld::Integer(65535), "19ffff", "65535"),
+    fixture("int-1000000", Ipld::Integer(1000000), "1a000f4240", "1000000"),
+    fixture("int-4294967296", Ipld::Integer(4294967296), "1b0000000100000000", "4294967296"),
+    fixture(
+      "int-1000000000000",
+      Ipld::Integer(1000000000000),
+      "1b000000e8d4a51000", "1000000000000",
+    ),
+    fixture(
+      "int-max-u64",
+      Ipld::Integer(u64::MAX.into()),
+      "1bffffffffffffffff", "18446744073709551615",
+    ),
+    fixture("int--1", Ipld::Integer(-1), "20", "-1"),
+    fixture("int--10", Ipld::Integer(-10), "29", "-10"),
+    fixture("int--100", Ipld::Integer(-100), "3863", "-100"),
+    fixture("int--1000", Ipld::Integer(-1000), "3903e7", "-1000"),
+    fixture(
+      "int-min-cbor",
+      Ipld::Integer(-1 - u64::MAX as i128),
+      "3bffffffffffffffff", "-18446744073709551616",
+    ),
+    fixture("float-1.1", Ipld::Float(1.1), "fb3ff199999999999a", "1.1"),
+    fixture("float-1e300", Ipld::Float(1.0e300), "fb7e37e43c8800759c", "1e300"),
+    fixture("float--4.1", Ipld::Float(-4.1), "fbc010666666666666", "-4.1"),
+    fixture("float-0", Ipld::Float(0.0), "fb0000000000000000", "0.0"),
+    fixture("string-empty", string(""), "60", r#""""#),
+    fixture("string-a", string("a"), "6161", r#""a""#),
+    fixture("string-ietf", string("IETF"), "6449455446", r#""IETF""#),
+    fixture("string-u-umlaut", string("\u{fc}"), "62c3bc", "\"\u{fc}\""),
+    fixture("string-water", string("\u{6c34}"), "63e6b0b4", "\"\u{6c34}\""),
+    fixture("bytes-empty", Ipld::Bytes(vec![]), "40", r#"{"/":{"bytes":""}}"#),
+    fixture("bytes", Ipld::Bytes(vec![1, 2, 3, 4]), "4401020304", r#"{"/":{"bytes":"AQIDBA"}}"#),
+    fixture("array-empty", Ipld::Array(vec![]), "80", "[]"),
+    fixture(
+      "array-nested",
+      Ipld::Array(vec![
+        Ipld::Integer(1),
+        Ipld::Array(vec![Ipld::Integer(2), Ipld::Integer(3)]),
+        Ipld::Array(vec![Ipld::Integer(4), Ipld::Integer(5)]),
+      ]),
+      "8301820203820405", "[1,[2,3],[4,5]]",
+    ),
+    fixture("map-empty", object(vec![]), "a0", "{}"),
+    fixture(
+      "map-nested",
+      object(vec![
+        ("a", Ipld::Integer(1)),
+        ("b", Ipld::Array(vec![Ipld::Integer(2), Ipld::Integer(3)])),
+      ]),
+      "a26161016162820203", r#"{"a":1,"b":[2,3]}"#,
+    ),
+    fixture(
+      "map-hello",
+      object(vec![("Hello", string("World"))]),
+      "a16548656c6c6f65576f726c64", r#"{"Hello":"World"}"#,
+    ),
+    fixture(
+      "map-length-first",
+      object(vec![("aa", Ipld::Integer(2)), ("b", Ipld::Integer(1))]),
+      "a261620162616102", r#"{"aa":2,"b":1}"#,
+    ),
+    fixture(
+      "map-length-then-bytes",
+      object(vec![
+        ("", Ipld::Null),
+        ("aaa", Ipld::Integer(3)),
+        ("ab", Ipld::Integer(2)),
+        ("b", Ipld::Integer(1)),
+        ("ba", Ipld::Integer(2)),
+        ("c", Ipld::Integer(1)),
+      ]),
+      "a660f661620161630162616202626261026361616103", r#"{"":null,"aaa":3,"ab":2,"b":1,"ba":2,"c":1}"#,
+    ),
+    fixture(
+      "map-keys-differ-in-case",
+      object(vec![("a", Ipld::Integer(1)), ("B", Ipld::Integer(2))]),
+      "a2614202616101", r#"{"B":2,"a":1}"#,
+    ),
+    fixture(
+      "link",
+      Ipld::Link(cid.clone()),
+      "d82a58250001711220f88bc853804cf294fe417e4fa83028689fcdb1b1592c5102e1474dbc200fab8b", r#"{"/":"bafyreihyrpefhacm6kkp4ql6j6udakdit7g3dmkzfriqfykhjw6cad5lrm"}"#,
+    ),
+    fixture(
+      "map-with-link",
+      object(vec![("link", Ipld::Link(cid)), ("name", string("x"))]),
+      "a2646c696e6bd82a58250001711220f88bc853804cf294fe417e4fa83028689fcdb1b1592c5102e1474dbc200fab8b646e616d656178", r#"{"link":{"/":"bafyreihyrpefhacm6kkp4ql6j6udakdit7g3dmkzfriqfykhjw6cad5lrm"},"name":"x"}"#,
+    ),
+  ]
+}
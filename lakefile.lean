@@ -14,6 +14,7 @@ require YatimaStdLib from git
   "https://github.com/yatima-inc/YatimaStdLib.lean" @ "649368d593f292227ab39b9fd08f6a448770dca8"
 
 lean_exe Tests.DagCbor
+lean_exe Tests.Keccak
 lean_exe Tests.Multibase
 lean_exe Tests.Multihash
 lean_exe Tests.UnsignedVarInt